@@ -0,0 +1,482 @@
+use crate::vars::Variables;
+
+/// How deeply variables may refer to other variables before evaluation gives up.
+const MAX_DEPTH: usize = 32;
+
+/// The operators of arithmetic expressions, longest first so they are matched greedily.
+const OPERATORS: [&str; 40] = [
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*=", "/=",
+    "%=", "+=", "-=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~",
+    "=", "?", ":", ",", "(", ")", "#",
+];
+
+/// The binary operators, from the loosest to the tightest binding.
+const BINARY_OPERATORS: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+const ASSIGNMENT_OPERATORS: [&str; 11] = [
+    "=", "*=", "/=", "%=", "+=", "-=", "<<=", ">>=", "&=", "^=", "|=",
+];
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+}
+
+/// Evaluates an arithmetic expression with 64-bit signed integers, as in `$(( ))`.
+///
+/// Variables are read as arithmetic expressions themselves, an unset or empty one being
+/// `0`, and can be assigned to with `=`, the compound assignment operators, `++` and `--`.
+/// Numbers may be written in decimal, in octal with a leading `0`, in hexadecimal with a
+/// leading `0x` or in any base from 2 to 64 as `base#digits`.
+///
+/// # Errors
+///
+/// Returns a message for syntax errors, division by zero and negative exponents.
+pub fn evaluate(expr: &str, vars: &mut Variables) -> Result<i64, String> {
+    evaluate_at_depth(expr, vars, 0)
+}
+
+fn evaluate_at_depth(expr: &str, vars: &mut Variables, depth: usize) -> Result<i64, String> {
+    if depth > MAX_DEPTH {
+        return Err("expression recursion level exceeded".to_string());
+    }
+
+    let mut evaluator = Evaluator {
+        tokens: tokenize(expr)?,
+        pos: 0,
+        vars,
+        depth,
+    };
+    if evaluator.tokens.is_empty() {
+        return Ok(0);
+    }
+
+    let value = evaluator.comma(false)?;
+    evaluator
+        .tokens
+        .get(evaluator.pos)
+        .map_or(Ok(value), |token| {
+            Err(format!(
+                "syntax error in expression (error token is \"{}\")",
+                describe(token)
+            ))
+        })
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(n) => n.to_string(),
+        Token::Name(name) => name.clone(),
+        Token::Operator(op) => (*op).to_string(),
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = expr.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '#' && c != '@' && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(parse_number(&rest[..len])?));
+            rest = &rest[len..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..len].to_string()));
+            rest = &rest[len..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Operator(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(format!(
+                "syntax error: operand expected (error token is \"{rest}\")"
+            ));
+        }
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// Parses an integer constant: decimal, octal (`017`), hexadecimal (`0x1f`) or
+/// `base#digits`.
+fn parse_number(s: &str) -> Result<i64, String> {
+    let invalid = || format!("{s}: invalid number");
+    let invalid_base = || format!("{s}: invalid arithmetic base");
+
+    let (base, digits) = if let Some((base, digits)) = s.split_once('#') {
+        let base = base.parse::<u32>().map_err(|_| invalid_base())?;
+        if !(2..=64).contains(&base) {
+            return Err(invalid_base());
+        }
+        (base, digits)
+    } else if let Some(digits) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        (16, digits)
+    } else if s.len() > 1 && s.starts_with('0') {
+        (8, &s[1..])
+    } else {
+        (10, s)
+    };
+
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        let digit = match c {
+            '0'..='9' => u32::from(c) - u32::from('0'),
+            'a'..='z' => u32::from(c) - u32::from('a') + 10,
+            'A'..='Z' if base <= 36 => u32::from(c) - u32::from('A') + 10,
+            'A'..='Z' => u32::from(c) - u32::from('A') + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(invalid()),
+        };
+        if digit >= base {
+            return Err(format!("{s}: value too great for base"));
+        }
+        value = value
+            .wrapping_mul(i64::from(base))
+            .wrapping_add(i64::from(digit));
+    }
+    Ok(value)
+}
+
+/// A recursive descent evaluator over the tokens of an expression.
+///
+/// Every method takes a `skip` flag, set for the operands that are not evaluated because of
+/// short-circuiting, so they are parsed without assigning variables or failing on division
+/// by zero.
+struct Evaluator<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    vars: &'a mut Variables,
+    depth: usize,
+}
+
+impl Evaluator<'_> {
+    fn peek_operator(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Operator(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.peek_operator() == Some(op) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("syntax error: '{op}' expected"))
+        }
+    }
+
+    /// `a, b`: evaluates both, returning `b`.
+    fn comma(&mut self, skip: bool) -> Result<i64, String> {
+        let mut value = self.assignment(skip)?;
+        while self.peek_operator() == Some(",") {
+            self.pos += 1;
+            value = self.assignment(skip)?;
+        }
+        Ok(value)
+    }
+
+    fn assignment(&mut self, skip: bool) -> Result<i64, String> {
+        let (Some(Token::Name(name)), Some(Token::Operator(op))) =
+            (self.tokens.get(self.pos), self.tokens.get(self.pos + 1))
+        else {
+            return self.conditional(skip);
+        };
+        if !ASSIGNMENT_OPERATORS.contains(op) {
+            return self.conditional(skip);
+        }
+        let (name, op) = (name.clone(), *op);
+        self.pos += 2;
+
+        let rhs = self.assignment(skip)?;
+        if skip {
+            return Ok(0);
+        }
+        let value = match op.strip_suffix('=').filter(|op| !op.is_empty()) {
+            Some(op) => apply(op, self.variable(&name)?, rhs)?,
+            None => rhs,
+        };
+        self.vars
+            .set(&name, value.to_string())
+            .map_err(|err| err.to_string())?;
+        Ok(value)
+    }
+
+    /// `cond ? a : b`
+    fn conditional(&mut self, skip: bool) -> Result<i64, String> {
+        let condition = self.binary(0, skip)?;
+        if self.peek_operator() != Some("?") {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let if_true = self.comma(skip || condition == 0)?;
+        self.expect(":")?;
+        let if_false = self.conditional(skip || condition != 0)?;
+        Ok(if condition == 0 { if_false } else { if_true })
+    }
+
+    /// The binary operators of `BINARY_OPERATORS[level]` and tighter binding ones.
+    fn binary(&mut self, level: usize, skip: bool) -> Result<i64, String> {
+        let Some(operators) = BINARY_OPERATORS.get(level) else {
+            return self.power(skip);
+        };
+
+        let mut lhs = self.binary(level + 1, skip)?;
+        while let Some(op) = self.peek_operator().filter(|op| operators.contains(op)) {
+            self.pos += 1;
+            lhs = match op {
+                "&&" => {
+                    let rhs = self.binary(level + 1, skip || lhs == 0)?;
+                    i64::from(lhs != 0 && rhs != 0)
+                }
+                "||" => {
+                    let rhs = self.binary(level + 1, skip || lhs != 0)?;
+                    i64::from(lhs != 0 || rhs != 0)
+                }
+                _ => {
+                    let rhs = self.binary(level + 1, skip)?;
+                    if skip {
+                        0
+                    } else {
+                        apply(op, lhs, rhs)?
+                    }
+                }
+            };
+        }
+        Ok(lhs)
+    }
+
+    /// `a ** b`, which groups from the right.
+    fn power(&mut self, skip: bool) -> Result<i64, String> {
+        let base = self.unary(skip)?;
+        if self.peek_operator() != Some("**") {
+            return Ok(base);
+        }
+        self.pos += 1;
+        let exponent = self.power(skip)?;
+        if skip {
+            Ok(0)
+        } else {
+            apply("**", base, exponent)
+        }
+    }
+
+    fn unary(&mut self, skip: bool) -> Result<i64, String> {
+        let Some(op) = self.peek_operator() else {
+            return self.postfix(skip);
+        };
+        match op {
+            "++" | "--" => {
+                self.pos += 1;
+                let Some(Token::Name(name)) = self.tokens.get(self.pos).cloned() else {
+                    return Err(format!("syntax error: variable expected after '{op}'"));
+                };
+                self.pos += 1;
+                if skip {
+                    return Ok(0);
+                }
+                let value = apply(&op[..1], self.variable(&name)?, 1)?;
+                self.vars
+                    .set(&name, value.to_string())
+                    .map_err(|err| err.to_string())?;
+                Ok(value)
+            }
+            "+" | "-" | "!" | "~" => {
+                self.pos += 1;
+                let value = self.unary(skip)?;
+                Ok(match op {
+                    "-" => value.wrapping_neg(),
+                    "!" => i64::from(value == 0),
+                    "~" => !value,
+                    _ => value,
+                })
+            }
+            _ => self.postfix(skip),
+        }
+    }
+
+    fn postfix(&mut self, skip: bool) -> Result<i64, String> {
+        match self.tokens.get(self.pos).cloned() {
+            Some(Token::Number(n)) => {
+                self.pos += 1;
+                Ok(n)
+            }
+            Some(Token::Name(name)) => {
+                self.pos += 1;
+                if skip {
+                    if matches!(self.peek_operator(), Some("++" | "--")) {
+                        self.pos += 1;
+                    }
+                    return Ok(0);
+                }
+                let value = self.variable(&name)?;
+                if let Some(op @ ("++" | "--")) = self.peek_operator() {
+                    self.pos += 1;
+                    self.vars
+                        .set(&name, apply(&op[..1], value, 1)?.to_string())
+                        .map_err(|err| err.to_string())?;
+                }
+                Ok(value)
+            }
+            Some(Token::Operator("(")) => {
+                self.pos += 1;
+                let value = self.comma(skip)?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            Some(token) => Err(format!(
+                "syntax error: operand expected (error token is \"{}\")",
+                describe(&token)
+            )),
+            None => Err("syntax error: operand expected".to_string()),
+        }
+    }
+
+    /// Reads a variable, evaluating its value as an expression.
+    fn variable(&mut self, name: &str) -> Result<i64, String> {
+        let value = self.vars.get(name).unwrap_or_default().to_string();
+        evaluate_at_depth(&value, self.vars, self.depth + 1)
+    }
+}
+
+/// Applies a binary operator, with wrapping on overflow.
+fn apply(op: &str, lhs: i64, rhs: i64) -> Result<i64, String> {
+    Ok(match op {
+        "*" => lhs.wrapping_mul(rhs),
+        "/" | "%" if rhs == 0 => return Err("division by 0".to_string()),
+        "/" => lhs.wrapping_div(rhs),
+        "%" => lhs.wrapping_rem(rhs),
+        "+" => lhs.wrapping_add(rhs),
+        "-" => lhs.wrapping_sub(rhs),
+        "**" => {
+            let exponent = u32::try_from(rhs).map_err(|_| {
+                if rhs < 0 {
+                    "exponent less than 0".to_string()
+                } else {
+                    "exponent too large".to_string()
+                }
+            })?;
+            lhs.wrapping_pow(exponent)
+        }
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        "<<" => lhs.wrapping_shl(rhs as u32),
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        ">>" => lhs.wrapping_shr(rhs as u32),
+        "<" => i64::from(lhs < rhs),
+        "<=" => i64::from(lhs <= rhs),
+        ">" => i64::from(lhs > rhs),
+        ">=" => i64::from(lhs >= rhs),
+        "==" => i64::from(lhs == rhs),
+        "!=" => i64::from(lhs != rhs),
+        "&" => lhs & rhs,
+        "^" => lhs ^ rhs,
+        "|" => lhs | rhs,
+        _ => return Err(format!("syntax error: unknown operator '{op}'")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expr: &str) -> Result<i64, String> {
+        evaluate(expr, &mut Variables::default())
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("2 + 3 * 4"), Ok(14));
+        assert_eq!(eval("(2 + 3) * 4"), Ok(20));
+        assert_eq!(eval("1 << 4 | 1"), Ok(17));
+        assert_eq!(eval("1 + 2 == 3 && 4 < 5"), Ok(1));
+        assert_eq!(eval("-2 ** 2"), Ok(4));
+        assert_eq!(eval("1 ? 2 : 3"), Ok(2));
+        assert_eq!(eval("1, 2"), Ok(2));
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        assert_eq!(eval("2 ** 3 ** 2"), Ok(512));
+        assert!(eval("2 ** -1").is_err());
+    }
+
+    #[test]
+    fn division_rounds_towards_zero() {
+        assert_eq!(eval("7 / 2"), Ok(3));
+        assert_eq!(eval("-7 % 3"), Ok(-1));
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert!(eval("1 / 0").unwrap_err().contains("division by 0"));
+        assert!(eval("5 % 0").unwrap_err().contains("division by 0"));
+        assert!(eval("x = 1, x /= 0").is_err());
+    }
+
+    #[test]
+    fn short_circuit_skips_side_effects() {
+        let mut vars = Variables::default();
+        assert_eq!(evaluate("0 && (x = 5)", &mut vars), Ok(0));
+        assert_eq!(evaluate("1 || (y = 5)", &mut vars), Ok(1));
+        assert_eq!(evaluate("1 ? 2 : (z = 5)", &mut vars), Ok(2));
+        assert_eq!(evaluate("0 || (w = 5)", &mut vars), Ok(1));
+        assert_eq!(vars.get("x"), None);
+        assert_eq!(vars.get("y"), None);
+        assert_eq!(vars.get("z"), None);
+        assert_eq!(vars.get("w"), Some("5"));
+    }
+
+    #[test]
+    fn skipped_division_by_zero_is_not_an_error() {
+        assert_eq!(eval("0 && 1 / 0"), Ok(0));
+        assert_eq!(eval("1 ? 1 : 1 / 0"), Ok(1));
+    }
+
+    #[test]
+    fn increments_and_variables() {
+        let mut vars = Variables::default();
+        vars.set("a", "3".to_string()).unwrap();
+        vars.set("b", "a + 1".to_string()).unwrap();
+        assert_eq!(evaluate("a++ + ++a", &mut vars), Ok(8));
+        assert_eq!(vars.get("a"), Some("5"));
+        assert_eq!(evaluate("b * 2", &mut vars), Ok(12));
+        assert_eq!(evaluate("unset", &mut vars), Ok(0));
+    }
+
+    #[test]
+    fn number_bases() {
+        assert_eq!(eval("010 + 0x10 + 2#101"), Ok(29));
+        assert_eq!(eval("64#_"), Ok(63));
+        assert!(eval("08").is_err());
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!(eval("1 +").is_err());
+        assert!(eval("(1").is_err());
+        assert!(eval("1 2").is_err());
+    }
+}
@@ -0,0 +1,323 @@
+use crate::lex::Span;
+use crate::parse::{Word, WordPart, WordPartKind};
+
+/// A piece of a word as seen by brace expansion: a character of unquoted literal text, with
+/// the span of the text it comes from, or any other part, which is never split.
+#[derive(Clone, Debug)]
+enum Item {
+    Char(char, Span),
+    Part(WordPart),
+}
+
+impl Item {
+    const fn span(&self) -> Span {
+        match self {
+            Self::Char(_, span) | Self::Part(WordPart { span, .. }) => *span,
+        }
+    }
+}
+
+/// Expands the brace expressions of a word into the words they produce, in order.
+///
+/// `{a,b,c}` produces one word for each comma-separated alternative, which may hold
+/// further brace expressions. `{x..y}` and `{x..y..step}` produce a sequence of integers,
+/// zero-padded to the same width if either end has a leading zero, or of letters. Quoted
+/// braces and commas are not special, and a brace expression that is neither of those is
+/// left as it is.
+#[must_use]
+pub fn expand(word: Word) -> Vec<Word> {
+    let span = word.span;
+    let mut items = vec![];
+    for part in word.parts {
+        match part {
+            WordPart {
+                kind: WordPartKind::Literal(text),
+                span,
+            } => items.extend(text.chars().map(|c| Item::Char(c, span))),
+            part => items.push(Item::Part(part)),
+        }
+    }
+
+    expand_items(&items)
+        .into_iter()
+        .map(|items| Word {
+            span,
+            ..to_word(items)
+        })
+        .collect()
+}
+
+fn expand_items(items: &[Item]) -> Vec<Vec<Item>> {
+    for (start, item) in items.iter().enumerate() {
+        if !matches!(item, Item::Char('{', _)) {
+            continue;
+        }
+        let Some((end, alternatives)) = brace_expression(items, start) else {
+            continue;
+        };
+
+        let suffixes = expand_items(&items[end + 1..]);
+        let mut ret = vec![];
+        for alternative in alternatives {
+            for middle in expand_items(&alternative) {
+                for suffix in &suffixes {
+                    let mut expanded = items[..start].to_vec();
+                    expanded.extend(middle.iter().cloned());
+                    expanded.extend(suffix.iter().cloned());
+                    ret.push(expanded);
+                }
+            }
+        }
+        return ret;
+    }
+
+    vec![items.to_vec()]
+}
+
+/// Parses the brace expression opened at `start`, returning the index of its closing brace
+/// and its alternatives, or `None` if the brace does not open a valid expression.
+fn brace_expression(items: &[Item], start: usize) -> Option<(usize, Vec<Vec<Item>>)> {
+    let mut depth = 0;
+    let mut commas = vec![];
+    let mut end = None;
+    for (i, item) in items.iter().enumerate().skip(start) {
+        match item {
+            Item::Char('{', _) => depth += 1,
+            Item::Char('}', _) => {
+                depth -= 1;
+                if depth == 0 {
+                    end = Some(i);
+                    break;
+                }
+            }
+            Item::Char(',', _) if depth == 1 => commas.push(i),
+            _ => (),
+        }
+    }
+    let end = end?;
+
+    if commas.is_empty() {
+        let text = items[start + 1..end]
+            .iter()
+            .map(|item| match item {
+                Item::Char(c, _) => Some(*c),
+                Item::Part(_) => None,
+            })
+            .collect::<Option<String>>()?;
+        // The terms come from the whole brace expression.
+        let span = items[start].span().to(items[end].span());
+        let sequence = sequence(&text)?
+            .into_iter()
+            .map(|term| term.chars().map(|c| Item::Char(c, span)).collect())
+            .collect();
+        return Some((end, sequence));
+    }
+
+    let bounds = std::iter::once(start)
+        .chain(commas)
+        .chain(std::iter::once(end))
+        .collect::<Vec<usize>>();
+    let alternatives = bounds
+        .windows(2)
+        .map(|window| items[window[0] + 1..window[1]].to_vec())
+        .collect();
+    Some((end, alternatives))
+}
+
+/// Expands the inside of a `{x..y}` or `{x..y..step}` sequence expression.
+fn sequence(text: &str) -> Option<Vec<String>> {
+    let mut bounds = text.split("..");
+    let (first, last) = (bounds.next()?, bounds.next()?);
+    let step = match bounds.next() {
+        Some(step) => step.parse::<i64>().ok()?.unsigned_abs().max(1),
+        None => 1,
+    };
+    if bounds.next().is_some() {
+        return None;
+    }
+
+    if let (Ok(first_n), Ok(last_n)) = (first.parse::<i64>(), last.parse::<i64>()) {
+        let padded = |s: &str| s.trim_start_matches('-').starts_with('0') && s.len() > 1;
+        let width = if padded(first) || padded(last) {
+            first.len().max(last.len())
+        } else {
+            0
+        };
+        return Some(
+            range(first_n, last_n, step)
+                .into_iter()
+                .map(|n| format!("{n:0width$}"))
+                .collect(),
+        );
+    }
+
+    let (mut first_c, mut last_c) = (first.chars(), last.chars());
+    match (first_c.next(), first_c.next(), last_c.next(), last_c.next()) {
+        (Some(first), None, Some(last), None)
+            if first.is_ascii_alphabetic() && last.is_ascii_alphabetic() =>
+        {
+            Some(
+                range(
+                    i64::from(u32::from(first)),
+                    i64::from(u32::from(last)),
+                    step,
+                )
+                .into_iter()
+                .filter_map(|c| char::from_u32(u32::try_from(c).ok()?))
+                .map(String::from)
+                .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Returns the numbers from `first` to `last`, counting down if `last` is smaller, and
+/// stepping by `step`.
+fn range(first: i64, last: i64, step: u64) -> Vec<i64> {
+    let step = i64::try_from(step).unwrap_or(i64::MAX);
+    let mut ret = vec![];
+    let mut n = first;
+    while (first <= last && n <= last) || (first > last && n >= last) {
+        ret.push(n);
+        let next = if first <= last {
+            n.checked_add(step)
+        } else {
+            n.checked_sub(step)
+        };
+        match next {
+            Some(next) => n = next,
+            None => break,
+        }
+    }
+    ret
+}
+
+/// Joins items back into a word, turning runs of characters into literal parts that span
+/// the text the characters come from.
+fn to_word(items: Vec<Item>) -> Word {
+    let mut word = Word::default();
+    let mut literal: Option<(String, Span)> = None;
+    for item in items {
+        match item {
+            Item::Char(c, span) => match &mut literal {
+                Some((text, literal_span)) => {
+                    text.push(c);
+                    *literal_span = literal_span.to(span);
+                }
+                None => literal = Some((c.to_string(), span)),
+            },
+            Item::Part(part) => {
+                word.parts.extend(literal.take().map(literal_part));
+                word.parts.push(part);
+            }
+        }
+    }
+    word.parts.extend(literal.map(literal_part));
+    word
+}
+
+fn literal_part((text, span): (String, Span)) -> WordPart {
+    WordPart {
+        kind: WordPartKind::Literal(text),
+        span,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(kind: WordPartKind) -> WordPart {
+        WordPart {
+            kind,
+            span: Span::default(),
+        }
+    }
+
+    fn literal(text: &str) -> Word {
+        Word {
+            parts: vec![part(WordPartKind::Literal(text.to_string()))],
+            ..Word::default()
+        }
+    }
+
+    fn text(word: &Word) -> String {
+        word.parts
+            .iter()
+            .map(|part| match &part.kind {
+                WordPartKind::Literal(text) | WordPartKind::SingleQuoted(text) => text.as_str(),
+                _ => "$",
+            })
+            .collect()
+    }
+
+    fn expand_text(word: &str) -> Vec<String> {
+        expand(literal(word)).iter().map(text).collect()
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(expand_text("a{b,c}d"), ["abd", "acd"]);
+        assert_eq!(expand_text("{,x}y"), ["y", "xy"]);
+        assert_eq!(expand_text("{a,b}{1,2}"), ["a1", "a2", "b1", "b2"]);
+    }
+
+    #[test]
+    fn nesting() {
+        assert_eq!(expand_text("{a,b{1,2}}x"), ["ax", "b1x", "b2x"]);
+        assert_eq!(expand_text("{{a,b}}"), ["{a}", "{b}"]);
+        assert_eq!(expand_text("{a,{1..2}}"), ["a", "1", "2"]);
+    }
+
+    #[test]
+    fn not_expanded() {
+        assert_eq!(expand_text("{a}"), ["{a}"]);
+        assert_eq!(expand_text("x{a,b"), ["x{a,b"]);
+        assert_eq!(expand_text("{a..3}"), ["{a..3}"]);
+        assert_eq!(expand_text("{1..2..3..4}"), ["{1..2..3..4}"]);
+    }
+
+    #[test]
+    fn quoted_parts_are_not_split() {
+        let word = Word {
+            parts: vec![
+                part(WordPartKind::Literal("{a".to_string())),
+                part(WordPartKind::SingleQuoted(",".to_string())),
+                part(WordPartKind::Literal("b,c}".to_string())),
+            ],
+            ..Word::default()
+        };
+        assert_eq!(
+            expand(word).iter().map(text).collect::<Vec<String>>(),
+            ["a,b", "c"]
+        );
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(expand_text("{1..3}"), ["1", "2", "3"]);
+        assert_eq!(expand_text("{3..1}"), ["3", "2", "1"]);
+        assert_eq!(expand_text("{-1..1}"), ["-1", "0", "1"]);
+        assert_eq!(expand_text("{a..e..2}"), ["a", "c", "e"]);
+        assert_eq!(expand_text("{c..a}"), ["c", "b", "a"]);
+    }
+
+    #[test]
+    fn padding() {
+        assert_eq!(expand_text("{01..3}"), ["01", "02", "03"]);
+        assert_eq!(expand_text("{8..010}"), ["008", "009", "010"]);
+        assert_eq!(expand_text("{-01..1}"), ["-01", "000", "001"]);
+    }
+
+    #[test]
+    fn steps() {
+        assert_eq!(expand_text("{1..10..3}"), ["1", "4", "7", "10"]);
+        assert_eq!(expand_text("{10..1..-3}"), ["10", "7", "4", "1"]);
+        assert_eq!(expand_text("{1..3..0}"), ["1", "2", "3"]);
+        assert_eq!(
+            expand_text("{9223372036854775806..9223372036854775807..5}"),
+            ["9223372036854775806"]
+        );
+    }
+}
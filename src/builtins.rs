@@ -0,0 +1,589 @@
+use crate::error::OsMessage;
+use crate::run::Interpreter;
+use crate::vars::{is_name, Variable, Variables};
+use crate::{arith, tilde};
+use std::fmt::Display;
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+/// A command run by the shell itself, taking its arguments and the streams it writes to,
+/// and returning its status.
+pub type Builtin = fn(&mut Interpreter, &[String], &mut Streams) -> i32;
+
+/// The output streams of a builtin, with the redirections of its command applied.
+pub struct Streams {
+    pub stdout: Box<dyn Write>,
+    pub stderr: Box<dyn Write>,
+}
+
+impl Streams {
+    /// Writes a line to stdout, returning the status of the builtin `name`: 1 if the line
+    /// could not be written, and 0 otherwise.
+    fn print(&mut self, name: &str, line: impl Display) -> i32 {
+        match writeln!(self.stdout, "{line}") {
+            Ok(()) => 0,
+            Err(err) => self.error(name, format_args!("write error: {}", OsMessage(&err))),
+        }
+    }
+
+    /// Reports an error of the builtin `name` to stderr, returning the status 1.
+    fn error(&mut self, name: &str, message: impl Display) -> i32 {
+        // There is nowhere left to report a failure to write the error.
+        let _ = writeln!(self.stderr, "qiish: {name}: {message}");
+        1
+    }
+}
+
+/// Returns the builtin called `name`, if there is one.
+pub fn find(name: &str) -> Option<Builtin> {
+    match name {
+        "cd" => Some(cd),
+        "dirs" => Some(dirs),
+        "exit" => Some(exit),
+        "export" => Some(export),
+        "let" => Some(let_),
+        "popd" => Some(popd),
+        "pushd" => Some(pushd),
+        "pwd" => Some(pwd),
+        "readonly" => Some(readonly),
+        "shopt" => Some(shopt),
+        "unset" => Some(unset),
+        _ => None,
+    }
+}
+
+/// `exit [n]`: exits the shell with the status `n`, modulo 256, or with the status of the
+/// last command.
+fn exit(interpreter: &mut Interpreter, args: &[String], streams: &mut Streams) -> i32 {
+    let status = match args {
+        [] => interpreter.status,
+        [n] => n.parse::<i64>().map_or_else(
+            |_| {
+                streams.error("exit", format_args!("{n}: numeric argument required"));
+                2
+            },
+            // Only the low 8 bits make it to the parent process.
+            |n| i32::try_from(n.rem_euclid(256)).unwrap_or_default(),
+        ),
+        _ => return streams.error("exit", "too many arguments"),
+    };
+    interpreter.exiting = Some(status);
+    status
+}
+
+/// `let expr...`: evaluates each argument as an arithmetic expression, succeeding if the
+/// last one is not zero.
+fn let_(interpreter: &mut Interpreter, args: &[String], streams: &mut Streams) -> i32 {
+    if args.is_empty() {
+        return streams.error("let", "expression expected");
+    }
+
+    let mut value = 0;
+    for arg in args {
+        match arith::evaluate(arg, &mut interpreter.vars) {
+            Ok(result) => value = result,
+            Err(message) => return streams.error("let", format_args!("{arg}: {message}")),
+        }
+    }
+    i32::from(value == 0)
+}
+
+/// `shopt [-s|-u] [name...]`: sets or unsets the named shell options, or prints them.
+fn shopt(interpreter: &mut Interpreter, args: &[String], streams: &mut Streams) -> i32 {
+    let (set, names) = match args.first().map(String::as_str) {
+        Some("-s") => (Some(true), &args[1..]),
+        Some("-u") => (Some(false), &args[1..]),
+        _ => (None, args),
+    };
+
+    let options = &mut interpreter.options;
+    let mut status = 0;
+    let names = if names.is_empty() {
+        vec![
+            "failglob".to_string(),
+            "ignoreeof".to_string(),
+            "nullglob".to_string(),
+        ]
+    } else {
+        names.to_vec()
+    };
+    for name in names {
+        let option = match name.as_str() {
+            "failglob" => &mut options.failglob,
+            "ignoreeof" => &mut options.ignoreeof,
+            "nullglob" => &mut options.nullglob,
+            _ => {
+                status = streams.error("shopt", format_args!("{name}: invalid shell option name"));
+                continue;
+            }
+        };
+        if let Some(value) = set {
+            *option = value;
+        } else {
+            let state = if *option { "on" } else { "off" };
+            status |= streams.print("shopt", format_args!("{name}\t{state}"));
+        }
+    }
+    status
+}
+
+/// Splits the leading options off the arguments of a builtin, returning their letters and
+/// the remaining arguments. `--` ends the options, and `-` is not one.
+fn split_options(args: &[String]) -> (String, &[String]) {
+    let mut letters = String::new();
+    for (i, arg) in args.iter().enumerate() {
+        if arg == "--" {
+            return (letters, &args[i + 1..]);
+        }
+        match arg.strip_prefix('-').filter(|options| !options.is_empty()) {
+            Some(options) => letters.push_str(options),
+            None => return (letters, &args[i..]),
+        }
+    }
+    (letters, &[])
+}
+
+/// Quotes a value so that the shell reads it back as it is.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// `export [-np] [name[=value]...]`: exports the variables to the environment of
+/// commands, after assigning them if a value is given, or stops exporting them with `-n`.
+///
+/// Without names, or with `-p`, prints the exported variables as commands that export them
+/// again.
+fn export(interpreter: &mut Interpreter, args: &[String], streams: &mut Streams) -> i32 {
+    let (options, names) = split_options(args);
+    let mut exported = true;
+    let mut print = names.is_empty();
+    for c in options.chars() {
+        match c {
+            'n' => exported = false,
+            'p' => print = true,
+            _ => return streams.error("export", format_args!("-{c}: invalid option")),
+        }
+    }
+
+    if print {
+        return print_declarations(interpreter, streams, "export", |variable| variable.exported);
+    }
+    declare(interpreter, streams, "export", names, |vars, name| {
+        vars.set_exported(name, exported);
+    })
+}
+
+/// `readonly [-p] [name[=value]...]`: makes the variables readonly, after assigning them if
+/// a value is given.
+///
+/// Without names, or with `-p`, prints the readonly variables as commands that make them
+/// readonly again.
+fn readonly(interpreter: &mut Interpreter, args: &[String], streams: &mut Streams) -> i32 {
+    let (options, names) = split_options(args);
+    if let Some(c) = options.chars().find(|&c| c != 'p') {
+        return streams.error("readonly", format_args!("-{c}: invalid option"));
+    }
+
+    if names.is_empty() || options.contains('p') {
+        return print_declarations(interpreter, streams, "readonly", |variable| {
+            variable.readonly
+        });
+    }
+    declare(
+        interpreter,
+        streams,
+        "readonly",
+        names,
+        Variables::set_readonly,
+    )
+}
+
+/// Assigns the `name=value` arguments of `export` and `readonly`, then sets the attributes
+/// of every named variable with `set_attributes`.
+fn declare(
+    interpreter: &mut Interpreter,
+    streams: &mut Streams,
+    builtin: &str,
+    args: &[String],
+    set_attributes: impl Fn(&mut Variables, &str),
+) -> i32 {
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = arg
+            .split_once('=')
+            .map_or((arg.as_str(), None), |(name, value)| (name, Some(value)));
+        if !is_name(name) {
+            status = streams.error(builtin, format_args!("{name}: not a valid identifier"));
+            continue;
+        }
+        if let Some(value) = value {
+            if let Err(err) = interpreter.vars.set(name, value.to_string()) {
+                status = streams.error(builtin, err);
+                continue;
+            }
+        }
+        set_attributes(&mut interpreter.vars, name);
+    }
+    status
+}
+
+/// Prints the variables picked by `filter` as `builtin` commands that declare them again.
+fn print_declarations(
+    interpreter: &Interpreter,
+    streams: &mut Streams,
+    builtin: &str,
+    filter: impl Fn(&Variable) -> bool,
+) -> i32 {
+    let mut status = 0;
+    for (name, variable) in interpreter.vars.sorted() {
+        if !filter(variable) {
+            continue;
+        }
+        status |= match &variable.value {
+            Some(value) => {
+                streams.print(builtin, format_args!("{builtin} {name}={}", quote(value)))
+            }
+            None => streams.print(builtin, format_args!("{builtin} {name}")),
+        };
+    }
+    status
+}
+
+/// `unset [-fv] name...`: unsets the variables, or with `-f` the functions, of those names.
+///
+/// There are no functions yet, so `-f` has nothing to unset.
+fn unset(interpreter: &mut Interpreter, args: &[String], streams: &mut Streams) -> i32 {
+    let (options, names) = split_options(args);
+    let mut functions = false;
+    for c in options.chars() {
+        match c {
+            'f' => functions = true,
+            'v' => functions = false,
+            _ => return streams.error("unset", format_args!("-{c}: invalid option")),
+        }
+    }
+    if functions {
+        return 0;
+    }
+
+    let mut status = 0;
+    for name in names {
+        if !is_name(name) {
+            status = streams.error("unset", format_args!("{name}: not a valid identifier"));
+        } else if interpreter.vars.unset(name).is_err() {
+            status = streams.error(
+                "unset",
+                format_args!("{name}: cannot unset: readonly variable"),
+            );
+        }
+    }
+    status
+}
+
+/// Splits the leading `-L` and `-P` options off the arguments of `cd` and `pwd`, returning
+/// whether the last one was `-P`, or `None` for an invalid option.
+fn physical_option(args: &[String]) -> Option<(bool, &[String])> {
+    let mut physical = false;
+    for (i, arg) in args.iter().enumerate() {
+        if arg == "--" {
+            return Some((physical, &args[i + 1..]));
+        }
+        if arg == "-" || !arg.starts_with('-') {
+            return Some((physical, &args[i..]));
+        }
+        for c in arg.chars().skip(1) {
+            match c {
+                'L' => physical = false,
+                'P' => physical = true,
+                _ => return None,
+            }
+        }
+    }
+    Some((physical, &[]))
+}
+
+/// `cd [-L|-P] [dir]`: changes the current directory, to `$HOME` by default and to
+/// `$OLDPWD` for `-`.
+///
+/// A relative directory is looked up in the directories of `$CDPATH` first. The new
+/// directory is printed if it was found there, or for `-`. With `-L`, the default, `..`
+/// goes back up the path that was followed to the current directory, symbolic links
+/// included, while `-P` resolves symbolic links first.
+fn cd(interpreter: &mut Interpreter, args: &[String], streams: &mut Streams) -> i32 {
+    let Some((physical, args)) = physical_option(args) else {
+        return streams.error("cd", "usage: cd [-L|-P] [dir]");
+    };
+    let (dir, mut print) = match args {
+        [] => match interpreter.vars.get("HOME") {
+            Some(home) => (home.to_string(), false),
+            None => return streams.error("cd", "HOME not set"),
+        },
+        [dir] if dir == "-" => match interpreter.vars.get("OLDPWD") {
+            Some(old) => (old.to_string(), true),
+            None => return streams.error("cd", "OLDPWD not set"),
+        },
+        [dir] => (dir.clone(), false),
+        _ => return streams.error("cd", "too many arguments"),
+    };
+
+    let mut target = dir.clone();
+    let first = dir.split('/').next().unwrap_or_default();
+    if !dir.starts_with('/') && first != "." && first != ".." {
+        let cdpath = interpreter.vars.get("CDPATH").unwrap_or_default();
+        for base in cdpath.split(':').filter(|_| !cdpath.is_empty()) {
+            let candidate = if base.is_empty() {
+                format!("./{dir}")
+            } else {
+                format!("{}/{dir}", base.trim_end_matches('/'))
+            };
+            if Path::new(&candidate).is_dir() {
+                target = candidate;
+                print |= !base.is_empty();
+                break;
+            }
+        }
+    }
+
+    if let Err(err) = change_dir(interpreter, &target, physical) {
+        return streams.error("cd", format_args!("{dir}: {}", OsMessage(&err)));
+    }
+    if print {
+        return streams.print("cd", current_pwd(interpreter));
+    }
+    0
+}
+
+/// Changes the current directory to `dir`, updating `$PWD` and `$OLDPWD`.
+///
+/// Unless `physical` is set, a relative `dir` is resolved against `$PWD`, and `..` removes
+/// the component before it rather than going to the parent of the directory it names.
+/// If that path cannot be entered, `dir` is entered as it is, like with `physical`.
+fn change_dir(interpreter: &mut Interpreter, dir: &str, physical: bool) -> std::io::Result<()> {
+    let old = current_pwd(interpreter);
+
+    let logical = if dir.starts_with('/') {
+        normalize(dir)
+    } else {
+        normalize(&format!("{old}/{dir}"))
+    };
+    let pwd = if !physical && std::env::set_current_dir(&logical).is_ok() {
+        logical
+    } else {
+        std::env::set_current_dir(dir)?;
+        physical_pwd()?
+    };
+
+    // The directory has changed all the same if they are readonly.
+    let _ = interpreter.vars.set("OLDPWD", old);
+    let _ = interpreter.vars.set("PWD", pwd);
+    Ok(())
+}
+
+/// Removes the `.` components of an absolute path, and the `..` components along with the
+/// component before each of them, without looking at the file system.
+fn normalize(path: &str) -> String {
+    let mut components = vec![];
+    for component in path.split('/') {
+        match component {
+            "" | "." => (),
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    format!("/{}", components.join("/"))
+}
+
+/// Returns the current directory with every symbolic link resolved.
+fn physical_pwd() -> std::io::Result<String> {
+    Ok(std::env::current_dir()?.to_string_lossy().into_owned())
+}
+
+/// Returns `$PWD` if it is an absolute path to the current directory, which is how `cd`
+/// leaves it, or else the physical path of the current directory.
+pub fn current_pwd(interpreter: &Interpreter) -> String {
+    interpreter
+        .vars
+        .get("PWD")
+        .filter(|pwd| is_current_dir(pwd))
+        .map_or_else(|| physical_pwd().unwrap_or_default(), str::to_string)
+}
+
+/// Returns whether `path` is an absolute path to the current directory.
+fn is_current_dir(path: &str) -> bool {
+    let (Ok(path_meta), Ok(cwd_meta)) = (std::fs::metadata(path), std::fs::metadata(".")) else {
+        return false;
+    };
+    path.starts_with('/') && path_meta.dev() == cwd_meta.dev() && path_meta.ino() == cwd_meta.ino()
+}
+
+/// `pwd [-L|-P]`: prints the current directory, as `$PWD` with `-L`, the default, or with
+/// every symbolic link resolved with `-P`.
+fn pwd(interpreter: &mut Interpreter, args: &[String], streams: &mut Streams) -> i32 {
+    let Some((physical, [])) = physical_option(args) else {
+        return streams.error("pwd", "usage: pwd [-L|-P]");
+    };
+    let dir = if physical {
+        match physical_pwd() {
+            Ok(dir) => dir,
+            Err(err) => return streams.error("pwd", err),
+        }
+    } else {
+        current_pwd(interpreter)
+    };
+    streams.print("pwd", dir)
+}
+
+/// Parses a `+n` or `-n` argument of `pushd`, `popd` and `dirs` into an index of the
+/// directory stack of `len` entries. `+n` counts from the top, starting at 0, and `-n`
+/// from the bottom.
+fn stack_index(arg: &str, len: usize) -> Option<usize> {
+    let n = arg[1..].parse::<usize>().ok()?;
+    match arg.chars().next()? {
+        '+' if n < len => Some(n),
+        '-' if n < len => Some(len - 1 - n),
+        _ => None,
+    }
+}
+
+/// Returns whether `arg` looks like a `+n` or `-n` argument.
+fn is_stack_index(arg: &str) -> bool {
+    arg.len() > 1
+        && (arg.starts_with('+') || arg.starts_with('-'))
+        && arg[1..].bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Returns the whole directory stack, whose top is the current directory.
+fn full_stack(interpreter: &Interpreter) -> Vec<String> {
+    std::iter::once(current_pwd(interpreter))
+        .chain(interpreter.dir_stack.iter().cloned())
+        .collect()
+}
+
+/// `pushd [dir | +n | -n]`: pushes `dir` on the directory stack and changes to it, rotates
+/// the stack so that its `n`th entry is on top, or without arguments swaps the top two
+/// entries. The stack is then printed.
+fn pushd(interpreter: &mut Interpreter, args: &[String], streams: &mut Streams) -> i32 {
+    let mut stack = full_stack(interpreter);
+    match args {
+        [] => {
+            if stack.len() < 2 {
+                return streams.error("pushd", "no other directory");
+            }
+            stack.swap(0, 1);
+        }
+        [arg] if is_stack_index(arg) => {
+            let Some(n) = stack_index(arg, stack.len()) else {
+                return streams.error(
+                    "pushd",
+                    format_args!("{arg}: directory stack index out of range"),
+                );
+            };
+            stack.rotate_left(n);
+        }
+        [dir] => stack.insert(0, dir.clone()),
+        _ => return streams.error("pushd", "too many arguments"),
+    }
+
+    if let Err(err) = change_dir(interpreter, &stack[0], false) {
+        return streams.error("pushd", format_args!("{}: {}", stack[0], OsMessage(&err)));
+    }
+    interpreter.dir_stack = stack.split_off(1);
+    print_stack(interpreter, streams, "pushd", DirsFormat::default())
+}
+
+/// `popd [+n | -n]`: removes the top entry of the directory stack and changes to the new
+/// top, or removes its `n`th entry. The stack is then printed.
+fn popd(interpreter: &mut Interpreter, args: &[String], streams: &mut Streams) -> i32 {
+    let mut stack = full_stack(interpreter);
+    if stack.len() < 2 {
+        return streams.error("popd", "directory stack empty");
+    }
+    let n = match args {
+        [] => 0,
+        [arg] if is_stack_index(arg) => match stack_index(arg, stack.len()) {
+            Some(n) => n,
+            None => {
+                return streams.error(
+                    "popd",
+                    format_args!("{arg}: directory stack index out of range"),
+                )
+            }
+        },
+        [arg] => return streams.error("popd", format_args!("{arg}: invalid argument")),
+        _ => return streams.error("popd", "too many arguments"),
+    };
+
+    stack.remove(n);
+    if n == 0 {
+        if let Err(err) = change_dir(interpreter, &stack[0], false) {
+            return streams.error("popd", format_args!("{}: {}", stack[0], OsMessage(&err)));
+        }
+    }
+    interpreter.dir_stack = stack.split_off(1);
+    print_stack(interpreter, streams, "popd", DirsFormat::default())
+}
+
+/// How `dirs` prints the directory stack.
+#[derive(Clone, Copy, Debug, Default)]
+struct DirsFormat {
+    /// Whether home directories are left as they are, rather than shortened to `~`.
+    long: bool,
+    /// Whether each entry is on a line of its own.
+    per_line: bool,
+    /// Whether each entry is on a line of its own, after its index.
+    numbered: bool,
+}
+
+/// `dirs [-clpv]`: prints the directory stack, from the top, or clears it with `-c`.
+fn dirs(interpreter: &mut Interpreter, args: &[String], streams: &mut Streams) -> i32 {
+    let mut format = DirsFormat::default();
+    for arg in args {
+        let Some(options) = arg.strip_prefix('-').filter(|options| !options.is_empty()) else {
+            return streams.error("dirs", format_args!("{arg}: invalid argument"));
+        };
+        for c in options.chars() {
+            match c {
+                'c' => interpreter.dir_stack.clear(),
+                'l' => format.long = true,
+                'p' => format.per_line = true,
+                'v' => format.numbered = true,
+                _ => return streams.error("dirs", format_args!("-{c}: invalid option")),
+            }
+        }
+    }
+    print_stack(interpreter, streams, "dirs", format)
+}
+
+fn print_stack(
+    interpreter: &Interpreter,
+    streams: &mut Streams,
+    name: &str,
+    format: DirsFormat,
+) -> i32 {
+    let entries = full_stack(interpreter).into_iter().map(|dir| {
+        if format.long {
+            dir
+        } else {
+            tilde::abbreviate(&dir, &interpreter.vars)
+        }
+    });
+
+    if format.numbered {
+        let mut status = 0;
+        for (i, dir) in entries.enumerate() {
+            status |= streams.print(name, format_args!("{i:2}  {dir}"));
+        }
+        status
+    } else if format.per_line {
+        let mut status = 0;
+        for dir in entries {
+            status |= streams.print(name, dir);
+        }
+        status
+    } else {
+        streams.print(name, entries.collect::<Vec<String>>().join(" "))
+    }
+}
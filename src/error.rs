@@ -0,0 +1,194 @@
+use crate::lex::Span;
+use std::{fmt, io};
+
+/// Everything that can go wrong while reading and running shell input.
+///
+/// [`Error::status`] is the one place errors are turned into exit statuses, and
+/// [`Error::report`] the one place they are printed.
+#[derive(Debug)]
+pub enum Error {
+    /// The input could not be split into tokens.
+    Lex(SyntaxError),
+    /// The tokens do not form a valid script.
+    Syntax(SyntaxError),
+    /// A word could not be expanded, as with a bad arithmetic expression.
+    Expansion(String),
+    /// A `${name:?message}` expansion of an unset parameter, which aborts the script.
+    UnsetParameter { name: String, message: String },
+    /// An assignment to, or unset of, the readonly variable of that name.
+    Readonly(String),
+    /// A redirection target could not be opened.
+    Redirection { target: String, source: io::Error },
+    /// A command name that is neither a builtin nor found in `$PATH`.
+    CommandNotFound(String),
+    /// A command that was found but could not be started.
+    Exec { command: String, source: io::Error },
+    /// The shell itself failed at some I/O, such as creating a pipe.
+    Io {
+        context: &'static str,
+        source: io::Error,
+    },
+    /// The shell was started with arguments it does not understand.
+    Usage(String),
+    /// The shell is exiting with a status, without anything to report.
+    Exit(i32),
+}
+
+impl Error {
+    /// The exit status this error results in: 2 for syntax and usage errors, 127 for
+    /// commands that cannot be found, 126 for commands that cannot be executed, and 1 for
+    /// everything else.
+    #[must_use]
+    pub fn status(&self) -> i32 {
+        match self {
+            Self::Lex(_) | Self::Syntax(_) | Self::Usage(_) => 2,
+            Self::CommandNotFound(_) => 127,
+            Self::Exec { source, .. } if source.kind() == io::ErrorKind::NotFound => 127,
+            Self::Exec { .. } => 126,
+            Self::Exit(status) => *status,
+            _ => 1,
+        }
+    }
+
+    /// Whether the error aborts the rest of the script, rather than only the command it
+    /// happened in.
+    #[must_use]
+    pub const fn is_fatal(&self) -> bool {
+        matches!(self, Self::UnsetParameter { .. } | Self::Exit(_))
+    }
+
+    /// Prints the error to stderr and returns its exit status.
+    // The status is not needed where the error happens off in a subshell.
+    #[allow(clippy::must_use_candidate)]
+    pub fn report(&self) -> i32 {
+        match self {
+            Self::Exit(_) => (),
+            // These already start with the name of the input.
+            Self::Lex(_) | Self::Syntax(_) => eprintln!("{self}"),
+            _ => eprintln!("qiish: {self}"),
+        }
+        self.status()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lex(err) | Self::Syntax(err) => write!(f, "{err}"),
+            Self::Expansion(message) | Self::Usage(message) => write!(f, "{message}"),
+            Self::UnsetParameter { name, message } => write!(f, "{name}: {message}"),
+            Self::Readonly(name) => write!(f, "{name}: readonly variable"),
+            Self::Redirection { target, source } => {
+                write!(f, "{target}: {}", OsMessage(source))
+            }
+            Self::CommandNotFound(command) => write!(f, "{command}: command not found"),
+            Self::Exec { command, source } => write!(f, "{command}: {}", OsMessage(source)),
+            Self::Io { context, source } => write!(f, "{context}: {}", OsMessage(source)),
+            Self::Exit(status) => write!(f, "exit {status}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Redirection { source, .. }
+            | Self::Exec { source, .. }
+            | Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Displays an I/O error the way other shells do, as the message of the OS without the
+/// `(os error N)` that Rust appends to it.
+pub(crate) struct OsMessage<'a>(pub &'a io::Error);
+
+impl fmt::Display for OsMessage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = self.0.to_string();
+        match self.0.raw_os_error() {
+            Some(code) => {
+                let suffix = format!(" (os error {code})");
+                write!(f, "{}", message.strip_suffix(&suffix).unwrap_or(&message))
+            }
+            None => write!(f, "{message}"),
+        }
+    }
+}
+
+/// A lex or syntax error, with where it was found.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct SyntaxError {
+    pub message: String,
+    pub span: Span,
+    /// The name of the input, such as the path of the script.
+    pub name: String,
+    /// The line of the input the error is on.
+    pub line: String,
+}
+
+impl SyntaxError {
+    /// Fills in the name of the input and the line of `source` the error is on.
+    #[must_use]
+    pub fn in_source(self, name: &str, source: &str) -> Self {
+        let line = source
+            .lines()
+            .nth(self.span.line.saturating_sub(1))
+            .unwrap_or_default()
+            .to_string();
+        Self {
+            name: name.to_string(),
+            line,
+            ..self
+        }
+    }
+}
+
+/// Renders the error as `name:line:column: message`, followed by the offending line with
+/// carets under the span.
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Span {
+            line,
+            column,
+            start,
+            end,
+        } = self.span;
+        // Tabs are kept so the carets line up with the text above them.
+        let indent = self
+            .line
+            .chars()
+            .take(column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let line_width = self
+            .line
+            .chars()
+            .count()
+            .saturating_sub(column.saturating_sub(1));
+        let width = (end - start).clamp(1, line_width.max(1));
+
+        write!(
+            f,
+            "{}:{line}:{column}: {}\n{}\n{indent}{}",
+            self.name,
+            self.message,
+            self.line,
+            "^".repeat(width)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn os_message_has_no_error_code() {
+        let err = io::Error::from_raw_os_error(libc::ENOENT);
+        assert_eq!(OsMessage(&err).to_string(), "No such file or directory");
+        let err = io::Error::other("custom");
+        assert_eq!(OsMessage(&err).to_string(), "custom");
+    }
+}
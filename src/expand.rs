@@ -0,0 +1,690 @@
+use crate::error::Error;
+use crate::parse::{Parameter, ParameterOp, ReplaceMode, Script, Word, WordPart, WordPartKind};
+use crate::run::{self, Interpreter};
+use crate::vars::is_name;
+use crate::{arith, glob, pattern, tilde};
+use std::io::Read;
+use std::mem::take;
+use std::sync::Arc;
+
+/// A piece of expanded text, remembering where it came from.
+#[derive(Clone, Debug, Default)]
+struct Segment {
+    text: String,
+    /// Whether the text came from a quoted part of the word.
+    quoted: bool,
+    /// Whether the text is the result of an unquoted expansion, and so subject to field
+    /// splitting.
+    split: bool,
+    /// Whether a field ends before the text, as between the positional parameters of
+    /// `"$@"`.
+    field_break: bool,
+}
+
+/// The characters fields are split on when `$IFS` is unset.
+const DEFAULT_IFS: &str = " \t\n";
+
+impl Segment {
+    /// The result of an expansion, which is split into fields unless it is quoted.
+    const fn expanded(text: String, quoted: bool) -> Self {
+        Self {
+            text,
+            quoted,
+            split: !quoted,
+            field_break: false,
+        }
+    }
+}
+
+impl Interpreter {
+    /// Expands a word into the fields it produces after field splitting and pathname
+    /// expansion.
+    ///
+    /// An unquoted expansion that is empty produces no field at all, while `""` produces
+    /// an empty one.
+    pub(crate) fn expand_word(&mut self, word: &Word) -> Result<Vec<String>, Error> {
+        let segments = self.expand_parts(&word.parts, false)?;
+        let ifs = self.vars.get("IFS").unwrap_or(DEFAULT_IFS).to_string();
+
+        let mut fields = vec![];
+        for field in split_fields(segments, &ifs) {
+            fields.extend(self.glob(field)?);
+        }
+        Ok(fields)
+    }
+
+    /// Replaces a field holding unquoted wildcards with the paths it matches.
+    ///
+    /// A pattern that matches nothing is kept as it is, unless the `nullglob` or `failglob`
+    /// options are set.
+    fn glob(&self, field: Vec<Segment>) -> Result<Vec<String>, Error> {
+        let is_pattern = field
+            .iter()
+            .any(|segment| !segment.quoted && pattern::has_wildcards(&segment.text));
+        if !is_pattern {
+            return Ok(vec![field
+                .into_iter()
+                .map(|segment| segment.text)
+                .collect()]);
+        }
+
+        let pattern = to_pattern(field.iter().cloned());
+        let paths = glob::expand(&pattern);
+        if !paths.is_empty() {
+            return Ok(paths);
+        }
+        if self.options.failglob {
+            return Err(Error::Expansion(format!("no match: {pattern}")));
+        }
+        if self.options.nullglob {
+            return Ok(vec![]);
+        }
+        Ok(vec![field
+            .into_iter()
+            .map(|segment| segment.text)
+            .collect()])
+    }
+
+    /// Expands a word into a single string, without field splitting.
+    pub(crate) fn expand_to_string(&mut self, word: &Word) -> Result<String, Error> {
+        Ok(join(self.expand_parts(&word.parts, false)?))
+    }
+
+    /// Expands a word into a pattern in which the quoted characters only match themselves.
+    fn expand_pattern(&mut self, word: &Word) -> Result<String, Error> {
+        Ok(to_pattern(self.expand_parts(&word.parts, false)?))
+    }
+
+    fn expand_parts(&mut self, parts: &[WordPart], quoted: bool) -> Result<Vec<Segment>, Error> {
+        let mut segments = vec![];
+        for part in parts {
+            match &part.kind {
+                WordPartKind::Literal(text) => segments.push(Segment {
+                    text: text.clone(),
+                    quoted,
+                    ..Segment::default()
+                }),
+                WordPartKind::SingleQuoted(text) => segments.push(Segment {
+                    text: text.clone(),
+                    quoted: true,
+                    ..Segment::default()
+                }),
+                WordPartKind::DoubleQuoted(parts) => {
+                    // Keeps `""` as an empty field rather than no field at all, except for
+                    // `"$@"` without positional parameters.
+                    if !(self.args.is_empty() && !parts.is_empty() && parts.iter().all(is_all_args))
+                    {
+                        segments.push(Segment {
+                            quoted: true,
+                            ..Segment::default()
+                        });
+                    }
+                    segments.extend(self.expand_parts(parts, true)?);
+                }
+                WordPartKind::Parameter(parameter) => {
+                    segments.extend(self.expand_parameter(parameter, quoted)?);
+                }
+                WordPartKind::CommandSubstitution(script) => {
+                    segments.push(Segment::expanded(self.substitute_command(script)?, quoted));
+                }
+                WordPartKind::Tilde(name) => segments.push(Segment {
+                    // The result is neither split nor globbed.
+                    text: tilde::expand(name, &self.vars).unwrap_or_else(|| format!("~{name}")),
+                    quoted: true,
+                    ..Segment::default()
+                }),
+                WordPartKind::Arithmetic(parts) => {
+                    let value = self.evaluate_arithmetic(parts)?;
+                    segments.push(Segment::expanded(value.to_string(), quoted));
+                }
+            }
+        }
+        Ok(segments)
+    }
+
+    fn expand_parameter(
+        &mut self,
+        parameter: &Parameter,
+        quoted: bool,
+    ) -> Result<Vec<Segment>, Error> {
+        let name = parameter.name.as_str();
+        let value = self.parameter(name);
+        let Some(op) = &parameter.op else {
+            // Every positional parameter is a field of its own.
+            if name == "@" || (name == "*" && !quoted) {
+                return Ok(self
+                    .args
+                    .iter()
+                    .enumerate()
+                    .map(|(i, arg)| Segment {
+                        field_break: i > 0,
+                        ..Segment::expanded(arg.clone(), quoted)
+                    })
+                    .collect());
+            }
+            return Ok(vec![Segment::expanded(value.unwrap_or_default(), quoted)]);
+        };
+
+        let text = match op {
+            ParameterOp::Length if name == "@" || name == "*" => self.args.len().to_string(),
+            ParameterOp::Length => value.unwrap_or_default().chars().count().to_string(),
+            ParameterOp::Default { colon, word } => {
+                if is_unset(value.as_deref(), *colon) {
+                    return self.expand_operand(word, quoted);
+                }
+                value.unwrap_or_default()
+            }
+            ParameterOp::Assign { colon, word } => {
+                if is_unset(value.as_deref(), *colon) {
+                    if !is_name(name) {
+                        return Err(Error::Expansion(format!(
+                            "${name}: cannot assign in this way"
+                        )));
+                    }
+                    let value = self.expand_to_string(word)?;
+                    self.vars.set(name, value.clone())?;
+                    value
+                } else {
+                    value.unwrap_or_default()
+                }
+            }
+            ParameterOp::Error { colon, word } => {
+                if is_unset(value.as_deref(), *colon) {
+                    let mut message = self.expand_to_string(word)?;
+                    if message.is_empty() {
+                        message = if *colon {
+                            "parameter null or not set".to_string()
+                        } else {
+                            "parameter not set".to_string()
+                        };
+                    }
+                    return Err(Error::UnsetParameter {
+                        name: name.to_string(),
+                        message,
+                    });
+                }
+                value.unwrap_or_default()
+            }
+            ParameterOp::Alternative { colon, word } => {
+                if !is_unset(value.as_deref(), *colon) {
+                    return self.expand_operand(word, quoted);
+                }
+                String::new()
+            }
+            ParameterOp::RemovePrefix { longest, pattern } => {
+                let pattern = self.expand_pattern(pattern)?;
+                remove_prefix(&value.unwrap_or_default(), &pattern, *longest)
+            }
+            ParameterOp::RemoveSuffix { longest, pattern } => {
+                let pattern = self.expand_pattern(pattern)?;
+                remove_suffix(&value.unwrap_or_default(), &pattern, *longest)
+            }
+            ParameterOp::Replace {
+                mode,
+                pattern,
+                replacement,
+            } => {
+                let pattern = self.expand_pattern(pattern)?;
+                let replacement = self.expand_to_string(replacement)?;
+                replace(&value.unwrap_or_default(), &pattern, &replacement, *mode)
+            }
+            ParameterOp::Substring { offset, length } => {
+                let offset = self.evaluate_arithmetic(&offset.parts)?;
+                let length = match length {
+                    Some(length) => Some(self.evaluate_arithmetic(&length.parts)?),
+                    None => None,
+                };
+                substring(&value.unwrap_or_default(), offset, length)
+            }
+            ParameterOp::Case {
+                upper,
+                all,
+                pattern,
+            } => {
+                let mut pattern = self.expand_pattern(pattern)?;
+                if pattern.is_empty() {
+                    pattern.push('?');
+                }
+                change_case(&value.unwrap_or_default(), &pattern, *upper, *all)
+            }
+        };
+
+        Ok(vec![Segment::expanded(text, quoted)])
+    }
+
+    /// Returns the value of a variable, or of a positional or special parameter.
+    fn parameter(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.status.to_string()),
+            "$" => Some(self.pid.to_string()),
+            "!" => self.last_background.map(|pid| pid.to_string()),
+            "#" => Some(self.args.len().to_string()),
+            "-" => Some(self.options.flags()),
+            "0" => Some(self.arg0.clone()),
+            "@" => Some(self.args.join(" ")),
+            "*" => {
+                // Joined by the first character of `$IFS`, or by nothing if it is empty.
+                let ifs = self.vars.get("IFS").unwrap_or(DEFAULT_IFS);
+                let separator = ifs.chars().next().map(String::from).unwrap_or_default();
+                Some(self.args.join(&separator))
+            }
+            // `${00}` is `$0`, like `${01}` is `$1`.
+            _ if name.starts_with(|c: char| c.is_ascii_digit()) => name
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| {
+                    n.checked_sub(1)
+                        .map_or(Some(&self.arg0), |i| self.args.get(i))
+                })
+                .cloned(),
+            _ => self.vars.get(name).map(str::to_string),
+        }
+    }
+
+    /// Expands the word of a `${name:-word}` or `${name:+word}` expansion, whose unquoted
+    /// parts are split into fields like the value of the parameter would be.
+    fn expand_operand(&mut self, word: &Word, quoted: bool) -> Result<Vec<Segment>, Error> {
+        let mut segments = self.expand_parts(&word.parts, quoted)?;
+        for segment in &mut segments {
+            segment.split = !segment.quoted;
+        }
+        Ok(segments)
+    }
+
+    /// Runs the script of a command substitution in a subshell and returns its output,
+    /// without trailing newlines.
+    ///
+    /// Its status is kept as the status of a command made only of assignments.
+    fn substitute_command(&mut self, script: &Script) -> Result<String, Error> {
+        let (mut reader, writer) = std::io::pipe().map_err(|source| Error::Io {
+            context: "cannot create pipe",
+            source,
+        })?;
+
+        // The output is read while the script runs, so it cannot fill up the pipe.
+        let output = std::thread::spawn(move || {
+            let mut output = vec![];
+            let _ = reader.read_to_end(&mut output);
+            output
+        });
+
+        // The subshell shares the current directory of the shell, so a `cd` in it is undone
+        // afterwards.
+        let dir = std::env::current_dir();
+        let mut subshell = self.clone();
+        subshell.stdout = Some(Arc::new(writer.into()));
+        let status = run::run(script, &mut subshell).unwrap_or_else(|err| err.report());
+        self.substitution_status = Some(status);
+        // Closes the write end, so the reader sees end of file.
+        drop(subshell);
+        if let Ok(dir) = dir {
+            let _ = std::env::set_current_dir(dir);
+        }
+
+        let output = output.join().unwrap_or_default();
+        let mut output = String::from_utf8_lossy(&output).into_owned();
+        output.truncate(output.trim_end_matches('\n').len());
+        Ok(output)
+    }
+
+    /// Expands and evaluates an arithmetic expression, as found in `$((...))`, `((...))` and
+    /// the offsets of `${name:offset:length}`.
+    pub(crate) fn evaluate_arithmetic(&mut self, parts: &[WordPart]) -> Result<i64, Error> {
+        let expr = join(self.expand_parts(parts, true)?);
+        arith::evaluate(&expr, &mut self.vars)
+            .map_err(|message| Error::Expansion(format!("{}: {message}", expr.trim())))
+    }
+}
+
+/// Joins segments into a single string, with a space at each field break.
+fn join(segments: Vec<Segment>) -> String {
+    let mut ret = String::new();
+    for segment in segments {
+        if segment.field_break {
+            ret.push(' ');
+        }
+        ret.push_str(&segment.text);
+    }
+    ret
+}
+
+/// Joins segments into a pattern in which the quoted characters only match themselves.
+fn to_pattern(segments: impl IntoIterator<Item = Segment>) -> String {
+    let mut ret = String::new();
+    for segment in segments {
+        if segment.field_break {
+            ret.push(' ');
+        }
+        if segment.quoted {
+            ret.push_str(&pattern::escape(&segment.text));
+        } else {
+            ret.push_str(&segment.text);
+        }
+    }
+    ret
+}
+
+/// Returns whether a part is a plain `$@`, which expands to nothing at all without
+/// positional parameters, even between double quotes.
+fn is_all_args(part: &WordPart) -> bool {
+    matches!(&part.kind, WordPartKind::Parameter(Parameter { name, op: None, .. }) if name == "@")
+}
+
+/// Returns whether a parameter counts as unset, which with `colon` includes being empty.
+fn is_unset(value: Option<&str>, colon: bool) -> bool {
+    value.is_none_or(|value| colon && value.is_empty())
+}
+
+/// Returns the byte offsets of every character boundary of `s`, including its end.
+fn boundaries(s: &str) -> Vec<usize> {
+    s.char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(s.len()))
+        .collect()
+}
+
+fn remove_prefix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut bounds = boundaries(value);
+    if longest {
+        bounds.reverse();
+    }
+    bounds
+        .into_iter()
+        .find(|&i| pattern::matches(pattern, &value[..i]))
+        .map_or_else(|| value.to_string(), |i| value[i..].to_string())
+}
+
+fn remove_suffix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut bounds = boundaries(value);
+    if !longest {
+        bounds.reverse();
+    }
+    bounds
+        .into_iter()
+        .find(|&i| pattern::matches(pattern, &value[i..]))
+        .map_or_else(|| value.to_string(), |i| value[..i].to_string())
+}
+
+fn replace(value: &str, pattern: &str, replacement: &str, mode: ReplaceMode) -> String {
+    let bounds = boundaries(value);
+
+    match mode {
+        ReplaceMode::Prefix => bounds
+            .iter()
+            .rev()
+            .find(|&&i| pattern::matches(pattern, &value[..i]))
+            .map_or_else(
+                || value.to_string(),
+                |&i| format!("{replacement}{}", &value[i..]),
+            ),
+        ReplaceMode::Suffix => bounds
+            .iter()
+            .find(|&&i| pattern::matches(pattern, &value[i..]))
+            .map_or_else(
+                || value.to_string(),
+                |&i| format!("{}{replacement}", &value[..i]),
+            ),
+        ReplaceMode::First | ReplaceMode::All => {
+            let mut ret = String::new();
+            let mut start = 0;
+            while start < bounds.len() - 1 {
+                let from = bounds[start];
+                // The longest match starting here.
+                let end = (start + 1..bounds.len())
+                    .rev()
+                    .find(|&end| pattern::matches(pattern, &value[from..bounds[end]]));
+                if let Some(end) = end {
+                    ret.push_str(replacement);
+                    start = end;
+                    if mode == ReplaceMode::First {
+                        break;
+                    }
+                } else {
+                    ret.push_str(&value[from..bounds[start + 1]]);
+                    start += 1;
+                }
+            }
+            ret.push_str(&value[bounds[start]..]);
+            ret
+        }
+    }
+}
+
+/// Returns `length` characters of `value` from `offset`. A negative offset counts from the
+/// end of the value, and a negative length is where to stop, counting from the end.
+fn substring(value: &str, offset: i64, length: Option<i64>) -> String {
+    let chars = value.chars().collect::<Vec<char>>();
+    let len = i64::try_from(chars.len()).unwrap_or(i64::MAX);
+
+    let start = if offset < 0 { len + offset } else { offset };
+    if !(0..=len).contains(&start) {
+        return String::new();
+    }
+    let end = match length {
+        None => len,
+        Some(length) if length < 0 => len + length,
+        Some(length) => len.min(start.saturating_add(length)),
+    };
+    if end <= start {
+        return String::new();
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    chars[start as usize..end as usize].iter().collect()
+}
+
+fn change_case(value: &str, pattern: &str, upper: bool, all: bool) -> String {
+    let mut ret = String::with_capacity(value.len());
+    for (i, c) in value.chars().enumerate() {
+        if (all || i == 0) && pattern::matches(pattern, c.encode_utf8(&mut [0; 4])) {
+            if upper {
+                ret.extend(c.to_uppercase());
+            } else {
+                ret.extend(c.to_lowercase());
+            }
+        } else {
+            ret.push(c);
+        }
+    }
+    ret
+}
+
+/// Splits the results of unquoted expansions on the characters of `ifs`.
+///
+/// Runs of IFS whitespace separate fields and are ignored at the start and end. Any other
+/// IFS character ends the current field, even if it is empty, absorbing the whitespace
+/// around it.
+fn split_fields(segments: Vec<Segment>, ifs: &str) -> Vec<Vec<Segment>> {
+    let mut fields = vec![];
+    let mut field: Vec<Segment> = vec![];
+    // Whether the current field holds anything, even an empty quoted string.
+    let mut started = false;
+    // Whether the last field was ended by whitespace, which a following non-whitespace
+    // delimiter joins rather than ending another, empty, field.
+    let mut after_whitespace = false;
+
+    for mut segment in segments {
+        // The segments of a field never break it.
+        if take(&mut segment.field_break) && started {
+            fields.push(take(&mut field));
+            started = false;
+            after_whitespace = false;
+        }
+        if !segment.split || ifs.is_empty() {
+            if segment.quoted || !segment.text.is_empty() {
+                started = true;
+                after_whitespace = false;
+            }
+            field.push(segment);
+            continue;
+        }
+
+        let mut text = String::new();
+        for c in segment.text.chars() {
+            if !ifs.contains(c) {
+                text.push(c);
+                after_whitespace = false;
+                continue;
+            }
+
+            if !text.is_empty() {
+                field.push(Segment {
+                    text: take(&mut text),
+                    ..segment.clone()
+                });
+                started = true;
+            }
+            if c.is_whitespace() {
+                if started {
+                    fields.push(take(&mut field));
+                    started = false;
+                    after_whitespace = true;
+                }
+            } else if !started && after_whitespace {
+                after_whitespace = false;
+            } else {
+                fields.push(take(&mut field));
+                started = false;
+            }
+        }
+        if !text.is_empty() {
+            field.push(Segment { text, ..segment });
+            started = true;
+        }
+    }
+
+    if started {
+        fields.push(field);
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::Span;
+    use crate::Options;
+
+    fn interpreter(args: &[&str]) -> Interpreter {
+        let mut interpreter = Interpreter::new(Options::default());
+        interpreter.set_args(
+            "script".to_string(),
+            args.iter().map(ToString::to_string).collect(),
+        );
+        interpreter
+    }
+
+    #[test]
+    fn positional_parameters() {
+        let interpreter = interpreter(&["a", "b"]);
+        assert_eq!(interpreter.parameter("1").as_deref(), Some("a"));
+        assert_eq!(interpreter.parameter("02").as_deref(), Some("b"));
+        assert_eq!(interpreter.parameter("3"), None);
+        assert_eq!(interpreter.parameter("#").as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn zero_positional_parameter_is_the_name() {
+        let interpreter = interpreter(&["a"]);
+        assert_eq!(interpreter.parameter("0").as_deref(), Some("script"));
+        assert_eq!(interpreter.parameter("00").as_deref(), Some("script"));
+        assert_eq!(interpreter.parameter("000").as_deref(), Some("script"));
+    }
+
+    #[test]
+    fn prefix_and_suffix_removal() {
+        assert_eq!(remove_prefix("a.b.c", "*.", false), "b.c");
+        assert_eq!(remove_prefix("a.b.c", "*.", true), "c");
+        assert_eq!(remove_suffix("a.b.c", ".*", false), "a.b");
+        assert_eq!(remove_suffix("a.b.c", ".*", true), "a");
+        assert_eq!(remove_prefix("a.b.c", "x", true), "a.b.c");
+    }
+
+    #[test]
+    fn removal_of_empty_and_matching_everything() {
+        assert_eq!(remove_prefix("abc", "", false), "abc");
+        assert_eq!(remove_suffix("abc", "", true), "abc");
+        assert_eq!(remove_prefix("abc", "*", false), "abc");
+        assert_eq!(remove_prefix("abc", "*", true), "");
+        assert_eq!(remove_suffix("", "*", true), "");
+    }
+
+    #[test]
+    fn removal_of_multibyte_characters() {
+        assert_eq!(remove_prefix("héllo", "h?", false), "llo");
+        assert_eq!(remove_suffix("héllo", "?", false), "héll");
+        assert_eq!(remove_suffix("héllo", "l*", false), "hél");
+        assert_eq!(remove_suffix("héllo", "l*", true), "hé");
+        assert_eq!(remove_prefix("日本語", "日", false), "本語");
+    }
+
+    #[test]
+    fn replacement() {
+        assert_eq!(replace("héllo", "é", "e", ReplaceMode::First), "hello");
+        assert_eq!(replace("héllo", "l", "L", ReplaceMode::First), "héLlo");
+        assert_eq!(replace("héllo", "l", "L", ReplaceMode::All), "héLLo");
+        assert_eq!(replace("héllo", "?", "x", ReplaceMode::All), "xxxxx");
+        assert_eq!(replace("héllo", "l*", "", ReplaceMode::First), "hé");
+        assert_eq!(replace("héllo", "h", "H", ReplaceMode::Prefix), "Héllo");
+        assert_eq!(replace("héllo", "l", "L", ReplaceMode::Prefix), "héllo");
+        assert_eq!(replace("héllo", "o", "O", ReplaceMode::Suffix), "héllO");
+        assert_eq!(replace("héllo", "x", "y", ReplaceMode::All), "héllo");
+    }
+
+    #[test]
+    fn replacement_of_empty_pattern() {
+        assert_eq!(replace("abc", "", "x", ReplaceMode::First), "abc");
+        assert_eq!(replace("abc", "", "x", ReplaceMode::All), "abc");
+        assert_eq!(replace("abc", "", "x", ReplaceMode::Prefix), "xabc");
+        assert_eq!(replace("abc", "", "x", ReplaceMode::Suffix), "abcx");
+        assert_eq!(replace("", "", "x", ReplaceMode::Prefix), "x");
+    }
+
+    #[test]
+    fn substrings() {
+        assert_eq!(substring("héllo", 1, Some(2)), "él");
+        assert_eq!(substring("héllo", -2, None), "lo");
+        assert_eq!(substring("héllo", 1, Some(-1)), "éll");
+        assert_eq!(substring("héllo", 2, Some(0)), "");
+        assert_eq!(substring("héllo", 10, None), "");
+        assert_eq!(substring("héllo", -10, None), "");
+        assert_eq!(substring("héllo", 0, Some(i64::MAX)), "héllo");
+    }
+
+    #[test]
+    fn case_changes() {
+        assert_eq!(change_case("héllo", "?", true, false), "Héllo");
+        assert_eq!(change_case("héllo", "?", true, true), "HÉLLO");
+        assert_eq!(change_case("héllo", "[lh]", true, true), "HéLLo");
+        assert_eq!(change_case("HÉLLO", "?", false, true), "héllo");
+    }
+
+    fn part(kind: WordPartKind) -> WordPart {
+        WordPart {
+            kind,
+            span: Span::default(),
+        }
+    }
+
+    fn quoted(parts: Vec<WordPart>) -> Word {
+        Word {
+            parts: vec![part(WordPartKind::DoubleQuoted(parts))],
+            ..Word::default()
+        }
+    }
+
+    #[test]
+    fn empty_quotes_without_positional_parameters() {
+        let mut interpreter = interpreter(&[]);
+        assert_eq!(interpreter.expand_word(&quoted(vec![])).unwrap(), [""]);
+        let all_args = part(WordPartKind::Parameter(Parameter {
+            name: "@".to_string(),
+            op: None,
+            span: Span::default(),
+        }));
+        assert!(interpreter
+            .expand_word(&quoted(vec![all_args]))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,65 @@
+use crate::pattern::{self, has_wildcards};
+use std::fs;
+
+/// Returns the paths matching a glob pattern, sorted.
+///
+/// The pattern is matched one `/`-separated component at a time. Wildcards never match a
+/// `/`, nor a leading `.` unless the component itself starts with one. Paths that do not
+/// exist are left out, so an empty result means nothing matched.
+#[must_use]
+pub fn expand(pattern: &str) -> Vec<String> {
+    let (mut paths, rest) = pattern.strip_prefix('/').map_or_else(
+        || (vec![String::new()], pattern),
+        |rest| (vec!["/".to_string()], rest),
+    );
+
+    let components = rest.split('/').collect::<Vec<&str>>();
+    for (i, component) in components.iter().enumerate() {
+        let mut matched = vec![];
+        for path in paths {
+            if has_wildcards(component) {
+                matched.extend(
+                    read_names(&path)
+                        .into_iter()
+                        .filter(|name| matches_name(component, name))
+                        .map(|name| format!("{path}{name}")),
+                );
+            } else {
+                matched.push(format!("{path}{}", pattern::unescape(component)));
+            }
+        }
+        if i + 1 < components.len() {
+            for path in &mut matched {
+                path.push('/');
+            }
+        }
+        paths = matched;
+    }
+
+    paths.retain(|path| fs::symlink_metadata(path).is_ok());
+    paths.sort();
+    paths
+}
+
+/// Returns the names of the entries of a directory, `path` being empty for the current
+/// directory. Names that are not valid UTF-8 are skipped.
+fn read_names(path: &str) -> Vec<String> {
+    let dir = if path.is_empty() { "." } else { path };
+    fs::read_dir(dir).map_or_else(
+        |_| vec![],
+        |entries| {
+            entries
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .collect()
+        },
+    )
+}
+
+/// Matches a file name against one component of a pattern. Hidden files are only matched
+/// by a component starting with a literal `.`.
+fn matches_name(component: &str, name: &str) -> bool {
+    if name.starts_with('.') && !(component.starts_with('.') || component.starts_with("\\.")) {
+        return false;
+    }
+    pattern::matches(component, name)
+}
@@ -4,19 +4,21 @@
 #![warn(clippy::cargo)]
 #![warn(clippy::suspicious)]
 
+use crate::error::{Error, SyntaxError};
 use crate::Options;
-use log::info;
-use logos::Logos;
-use std::fs::File;
-use std::io::Read;
+use logos::{Lexer, Logos, Skip};
+use std::fmt;
+use std::ops::Range;
 
 #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash, Logos)]
+#[logos(extras = LexerState)]
 pub enum Token {
     // Redirection
     #[token("|")]
     Pipe,
-    #[token("<<")]
-    HereDoc,
+    #[token("<<", |lex| lex_here_doc(lex, false))]
+    #[token("<<-", |lex| lex_here_doc(lex, true))]
+    HereDoc(HereDoc),
     #[token("<<<")]
     HereString,
     #[token(">")]
@@ -27,36 +29,657 @@ pub enum Token {
     RedirAppend,
     #[token("<")]
     RedirInput,
+    #[token("<>")]
+    RedirReadWrite,
+    #[token("<&")]
+    RedirDupInput,
+    #[token(">&")]
+    RedirDupOutput,
+    #[token("&>")]
+    RedirAll,
+    #[token("&>>")]
+    RedirAllAppend,
 
-    // Double-quoted string
-    #[regex(r#""(\\.|[^"\\])*""#, |lex| lex.slice().to_string())]
-    DQString(String),
-    // Single-quoted string
-    #[regex(r#"'(\\.|[^'\\])*'"#, |lex| lex.slice().to_string())]
-    SQString(String),
+    // Arithmetic command
+    #[token("((", lex_arithmetic_command)]
+    Arithmetic(String),
 
     // After Command
     #[token("&&")]
     AndAnd,
     #[token("||")]
     OrOr,
+    #[token(";")]
+    Semi,
+    #[token("&")]
+    Amp,
+    #[token("\n", read_here_doc_bodies)]
+    Newline,
 
     // Special
-    // TODO: Implement `Eof` token
-    #[allow(dead_code)]
+    // End of the input, added by `lex` after the last token
     Eof,
-    #[regex(r"[a-zA-Z_][a-zA-Z0-9_]*", |lex| lex.slice().to_string())]
+    // Word, with its quotes and escapes still in place. A `#` only starts a comment at
+    // the start of a word.
+    #[regex(r"[^|&;<>() \t\n#]", lex_text)]
     Text(String),
-    #[regex(r"[ ]+")]
+    #[regex(r"[ \t]+")]
     Space,
     #[error]
-    #[regex(r"[\t\f\n]+", logos::skip)]
+    #[regex(r"#[^\n]*", logos::skip)]
+    #[token("\\\n", skip_continuation)]
     Error,
 }
 
-pub type Tokens = Vec<Token>;
+/// Returns whether `c` is a metacharacter, which ends a word unless it is quoted.
+const fn is_metachar(c: char) -> bool {
+    matches!(
+        c,
+        '|' | '&' | ';' | '<' | '>' | '(' | ')' | ' ' | '\t' | '\n'
+    )
+}
+
+/// Reads the rest of a word: a run of characters other than unquoted metacharacters.
+///
+/// Quoted strings, backslash escapes and `${...}`, `$(...)` and `` `...` `` expansions may
+/// appear anywhere in the word, and are read as a whole even if they hold metacharacters.
+/// Fails if one of them is never closed.
+fn lex_text(lex: &mut Lexer<Token>) -> Option<String> {
+    let text = &lex.source()[lex.span().start..];
+
+    let mut len = 0;
+    while let Some(c) = text[len..].chars().next() {
+        if is_metachar(c) {
+            break;
+        }
+        len += nested_len(&text[len..])?;
+    }
+
+    lex.bump(len - lex.slice().len());
+    if lex.slice().ends_with("\\\n") {
+        lex.extras.continuation_end = Some(lex.span().end);
+    }
+    Some(lex.slice().to_string())
+}
+
+/// Skips a backslash-newline line continuation between words.
+fn skip_continuation(lex: &mut Lexer<Token>) -> Skip {
+    lex.extras.continuation_end = Some(lex.span().end);
+    Skip
+}
+
+/// Reads an `(( expr ))` arithmetic command, returning the expression between the
+/// parentheses.
+fn lex_arithmetic_command(lex: &mut Lexer<Token>) -> Option<String> {
+    let text = &lex.source()[lex.span().start..];
+    let expr = arithmetic_expression(text)?;
+    lex.bump(expr.len() + 2);
+    Some(expr.to_string())
+}
+
+/// Returns the expression of the `((expr))` group at the start of `s`, or `None` if the
+/// group is not closed by a `))`, as with `((a) (b))`.
+pub(crate) fn arithmetic_expression(s: &str) -> Option<&str> {
+    let len = paren_len(s)?;
+    (s.starts_with("((") && paren_len(&s[1..]) == Some(len - 2)).then(|| &s[2..len - 2])
+}
+
+/// Returns the length of the escaped character, quoted string or expansion at the start of
+/// `s`, or of its first character if it is none of those.
+///
+/// Returns `None` if `s` is empty or starts with something that is never closed.
+pub(crate) fn nested_len(s: &str) -> Option<usize> {
+    let c = s.chars().next()?;
+    let rest = &s[c.len_utf8()..];
+    match c {
+        '\\' => Some(1 + rest.chars().next().map_or(0, char::len_utf8)),
+        '\'' => Some(2 + rest.find('\'')?),
+        '"' => Some(2 + closing_quote(rest)?),
+        '`' => Some(2 + closing_backquote(rest)?),
+        '$' if rest.starts_with('{') => Some(1 + braced_len(rest)?),
+        '$' if rest.starts_with('(') => Some(1 + paren_len(rest)?),
+        _ => Some(c.len_utf8()),
+    }
+}
+
+/// Returns the index of the `"` ending the double-quoted text `s` starts with, skipping
+/// escaped characters and expansions.
+pub(crate) fn closing_quote(s: &str) -> Option<usize> {
+    let mut i = 0;
+    while let Some(c) = s[i..].chars().next() {
+        match c {
+            '"' => return Some(i),
+            // Single quotes are not special between double quotes.
+            '\'' => i += 1,
+            _ => i += nested_len(&s[i..])?,
+        }
+    }
+    None
+}
+
+/// Returns the index of the `` ` `` ending the command substitution `s` starts with.
+pub(crate) fn closing_backquote(s: &str) -> Option<usize> {
+    let mut i = 0;
+    while let Some(c) = s[i..].chars().next() {
+        match c {
+            '`' => return Some(i),
+            '\\' => i += 1 + s[i + 1..].chars().next().map_or(0, char::len_utf8),
+            _ => i += c.len_utf8(),
+        }
+    }
+    None
+}
+
+/// Returns the length of the `{...}` group at the start of `s`, up to and including the
+/// brace closing it. Quoted text and nested expansions are skipped over.
+///
+/// Returns `None` if the group is never closed.
+pub(crate) fn braced_len(s: &str) -> Option<usize> {
+    let mut i = 1;
+    while let Some(c) = s[i..].chars().next() {
+        match c {
+            '}' => return Some(i + 1),
+            _ => i += nested_len(&s[i..])?,
+        }
+    }
+    None
+}
+
+/// Returns the length of the `(...)` group at the start of `s`, up to and including the
+/// parenthesis closing it. Quoted text and nested expansions are skipped over.
+///
+/// Returns `None` if the group is never closed.
+pub(crate) fn paren_len(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = 0;
+    while let Some(c) = s[i..].chars().next() {
+        match c {
+            '(' => {
+                depth += 1;
+                i += 1;
+            }
+            ')' => {
+                depth -= 1;
+                i += 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => i += nested_len(&s[i..])?,
+        }
+    }
+    None
+}
+
+/// A `<<WORD` here-document. The body is filled in from the lines following the operator.
+#[derive(Clone, Debug, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct HereDoc {
+    /// The delimiter with its quotes removed.
+    pub delimiter: String,
+    /// Whether any part of the delimiter was quoted, which suppresses expansion of the body.
+    pub quoted: bool,
+    /// Whether leading tabs are stripped from the body and delimiter lines (`<<-`).
+    pub strip_tabs: bool,
+    /// The lines between the operator and the delimiter line.
+    pub body: String,
+    /// Where the body is in the input, filled in by [`lex`]. With `<<-`, the body also
+    /// covers the stripped tabs.
+    pub body_span: Span,
+}
+
+/// State shared between the lexer callbacks.
+#[derive(Clone, Debug, Default)]
+pub struct LexerState {
+    /// Here-documents whose operator has been seen, but whose body has not been read yet.
+    pending: Vec<HereDoc>,
+    /// Here-documents whose body has been read, in the order the operators appeared.
+    read: Vec<HereDoc>,
+    /// Where the last backslash-newline line continuation ends, between words or inside
+    /// one. Those in quotes and comments do not count.
+    continuation_end: Option<usize>,
+}
+
+/// Reads the delimiter word following a `<<` or `<<-` operator.
+fn lex_here_doc(lex: &mut Lexer<Token>, strip_tabs: bool) -> Option<HereDoc> {
+    let remainder = lex.remainder();
+    let start = remainder.len() - remainder.trim_start_matches([' ', '\t']).len();
+
+    let mut here_doc = HereDoc {
+        strip_tabs,
+        ..HereDoc::default()
+    };
+    let mut chars = remainder[start..].char_indices();
+    let mut end = remainder.len() - start;
+    while let Some((i, c)) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' | ';' | '&' | '|' | '<' | '>' | '(' | ')' => {
+                end = i;
+                break;
+            }
+            '\'' | '"' => {
+                here_doc.quoted = true;
+                let closed = chars.by_ref().any(|(_, next)| {
+                    if next == c {
+                        return true;
+                    }
+                    here_doc.delimiter.push(next);
+                    false
+                });
+                if !closed {
+                    return None;
+                }
+            }
+            '\\' => {
+                here_doc.quoted = true;
+                if let Some((_, next)) = chars.next() {
+                    here_doc.delimiter.push(next);
+                }
+            }
+            _ => here_doc.delimiter.push(c),
+        }
+    }
+
+    if here_doc.delimiter.is_empty() && !here_doc.quoted {
+        return None;
+    }
+
+    lex.bump(start + end);
+    lex.extras.pending.push(here_doc.clone());
+    Some(here_doc)
+}
+
+/// Reads the bodies of the pending here-documents from the lines following a newline.
+fn read_here_doc_bodies(lex: &mut Lexer<Token>) {
+    let pending = std::mem::take(&mut lex.extras.pending);
+    let start = lex.span().end;
+    let mut consumed = 0;
+
+    for mut here_doc in pending {
+        let mut found = false;
+        here_doc.body_span.start = start + consumed;
+        here_doc.body_span.end = start + consumed;
+        for line in lex.remainder()[consumed..].split_inclusive('\n') {
+            consumed += line.len();
+            let line = if here_doc.strip_tabs {
+                line.trim_start_matches('\t')
+            } else {
+                line
+            };
+            if line.strip_suffix('\n').unwrap_or(line) == here_doc.delimiter {
+                found = true;
+                break;
+            }
+            here_doc.body.push_str(line);
+            here_doc.body_span.end = start + consumed;
+        }
+
+        if found {
+            lex.extras.read.push(here_doc);
+        } else {
+            // The input ran out before the delimiter line.
+            lex.extras.pending.push(here_doc);
+        }
+    }
+
+    lex.bump(consumed);
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pipe => write!(f, "|"),
+            Self::HereDoc(here_doc) => {
+                let operator = if here_doc.strip_tabs { "<<-" } else { "<<" };
+                write!(f, "{operator}{}", here_doc.delimiter)
+            }
+            Self::HereString => write!(f, "<<<"),
+            Self::Redir => write!(f, ">"),
+            Self::RedirClobber => write!(f, ">|"),
+            Self::RedirAppend => write!(f, ">>"),
+            Self::RedirInput => write!(f, "<"),
+            Self::RedirReadWrite => write!(f, "<>"),
+            Self::RedirDupInput => write!(f, "<&"),
+            Self::RedirDupOutput => write!(f, ">&"),
+            Self::RedirAll => write!(f, "&>"),
+            Self::RedirAllAppend => write!(f, "&>>"),
+            Self::Arithmetic(expr) => write!(f, "(({expr}))"),
+            Self::AndAnd => write!(f, "&&"),
+            Self::OrOr => write!(f, "||"),
+            Self::Semi => write!(f, ";"),
+            Self::Amp => write!(f, "&"),
+            Self::Newline => write!(f, "newline"),
+            Self::Text(s) => write!(f, "{s}"),
+            Self::Eof => write!(f, "end of file"),
+            Self::Space => write!(f, " "),
+            Self::Error => write!(f, "invalid token"),
+        }
+    }
+}
+
+/// Where a token or syntax tree node is in the input: a range of bytes, and the line and
+/// column it starts at, both counted from 1. Columns count characters, not bytes.
+#[derive(Copy, Clone, Debug, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// Returns the span from the start of `self` to the end of `other`.
+    #[must_use]
+    pub const fn to(self, other: Self) -> Self {
+        Self {
+            end: other.end,
+            ..self
+        }
+    }
+
+    /// Returns the span of `text[range]`, where `self` is the span of `text`.
+    #[must_use]
+    pub fn slice(self, text: &str, range: Range<usize>) -> Self {
+        let before = &text[..range.start];
+        let (line, column) = before.rfind('\n').map_or_else(
+            || (self.line, self.column + before.chars().count()),
+            |i| {
+                (
+                    self.line + before.matches('\n').count(),
+                    before[i + 1..].chars().count() + 1,
+                )
+            },
+        );
+        Self {
+            start: self.start + range.start,
+            end: self.start + range.end,
+            line,
+            column,
+        }
+    }
+}
+
+/// Turns byte ranges of an input into [`Span`]s.
+struct Locator<'a> {
+    input: &'a str,
+    /// The byte offset of the start of every line.
+    line_starts: Vec<usize>,
+    /// Where the input starts, when it is part of a larger one.
+    origin: Span,
+}
+
+impl<'a> Locator<'a> {
+    fn new(input: &'a str, origin: Span) -> Self {
+        Self {
+            input,
+            line_starts: std::iter::once(0)
+                .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+                .collect(),
+            origin,
+        }
+    }
+
+    fn span(&self, range: Range<usize>) -> Span {
+        let line = self
+            .line_starts
+            .partition_point(|&start| start <= range.start);
+        let column = self.input[self.line_starts[line - 1]..range.start]
+            .chars()
+            .count();
+        Span {
+            start: self.origin.start + range.start,
+            end: self.origin.start + range.end,
+            line: self.origin.line + line - 1,
+            column: if line == 1 {
+                self.origin.column + column
+            } else {
+                column + 1
+            },
+        }
+    }
+}
+
+/// Every token of the input with its span, ending with a [`Token::Eof`].
+pub type Tokens = Vec<(Token, Span)>;
+
+/// Lexes the input, filling in the bodies of any here-documents.
+///
+/// A here-document whose delimiter line is missing takes the rest of the input as its body,
+/// with a warning. The [`Token::Eof`] at the end is placed after the last line that is not
+/// empty, so errors about it point at that line.
+///
+/// # Errors
+///
+/// Returns [`Error::Lex`], pointing into the input called `name`, for text that is not a
+/// token, such as an unterminated quote.
+pub fn lex(input: &str, name: &str, _options: Options) -> Result<Tokens, Error> {
+    let origin = Span {
+        line: 1,
+        column: 1,
+        ..Span::default()
+    };
+    lex_at(input, origin).map_err(|err| Error::Lex(err.in_source(name, input)))
+}
+
+/// Lexes the input like [`lex`], where `origin` is where the input starts in the source it
+/// was taken from, such as the body of a command substitution. The spans of the tokens and of
+/// any error point into that source.
+pub(crate) fn lex_at(input: &str, origin: Span) -> Result<Tokens, SyntaxError> {
+    let locator = Locator::new(input, origin);
+    let mut lexer = Token::lexer(input);
+    let mut tokens = Tokens::new();
+    while let Some(token) = lexer.next() {
+        if token == Token::Error {
+            let (message, range) = describe_error(input, lexer.span());
+            return Err(SyntaxError {
+                message,
+                span: locator.span(range),
+                ..SyntaxError::default()
+            });
+        }
+        tokens.push((token, locator.span(lexer.span())));
+    }
+    let end = input.trim_end_matches('\n').len();
+    tokens.push((Token::Eof, locator.span(end..end)));
+
+    let LexerState { pending, read, .. } = lexer.extras;
+    let mut bodies = read
+        .into_iter()
+        .chain(pending.into_iter().inspect(|here_doc| {
+            eprintln!(
+                "qiish: warning: here-document delimited by end of file (wanted '{}')",
+                here_doc.delimiter
+            );
+        }));
+    for (token, _) in &mut tokens {
+        if let Token::HereDoc(here_doc) = token {
+            if let Some(mut read) = bodies.next() {
+                let Span { start, end, .. } = read.body_span;
+                read.body_span = locator.span(start..end);
+                *here_doc = read;
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Explains why the text at `range` is not a token, returning the message and the range of
+/// the offending construct.
+fn describe_error(input: &str, range: Range<usize>) -> (String, Range<usize>) {
+    let text = &input[range.start..];
+    if text.starts_with("<<") {
+        return ("missing here-document delimiter".to_string(), range);
+    }
+    if text.starts_with("((") {
+        return ("unterminated arithmetic command".to_string(), range);
+    }
+
+    // Finds the quote or expansion of the word that is never closed.
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        if is_metachar(c) {
+            break;
+        }
+        let Some(len) = nested_len(&text[i..]) else {
+            let what = match c {
+                '\'' => "single quote",
+                '"' => "double quote",
+                '`' => "backquote",
+                _ if text[i..].starts_with("${") => "parameter expansion",
+                _ => "command substitution",
+            };
+            let start = range.start + i;
+            return (format!("unterminated {what}"), start..start + 1);
+        };
+        i += len;
+    }
+
+    let c = text.chars().next().unwrap_or_default();
+    (format!("unexpected '{c}'"), range)
+}
+
+/// Returns whether the input ends inside a here-document or with a backslash-newline line
+/// continuation, so more lines need to be read.
+#[must_use]
+pub fn needs_more_input(input: &str) -> bool {
+    let mut lexer = Token::lexer(input);
+    lexer.by_ref().for_each(drop);
+    !lexer.extras.pending.is_empty() || lexer.extras.continuation_end == Some(input.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<Token> {
+        lex(input, "test", Options::default())
+            .unwrap()
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect()
+    }
+
+    fn text(s: &str) -> Token {
+        Token::Text(s.to_string())
+    }
+
+    #[test]
+    fn quotes_and_escapes_stay_in_words() {
+        assert_eq!(
+            tokens(r#"a"b c"d\ e 'f;g'"#),
+            [
+                text(r#"a"b c"d\ e"#),
+                Token::Space,
+                text("'f;g'"),
+                Token::Eof
+            ]
+        );
+        assert_eq!(
+            tokens(r#""a\"b"'c'\'|d"#),
+            [text(r#""a\"b"'c'\'"#), Token::Pipe, text("d"), Token::Eof]
+        );
+        assert_eq!(
+            tokens("echo $(a | b)\"${c:-d e}\"`f;g`"),
+            [
+                text("echo"),
+                Token::Space,
+                text("$(a | b)\"${c:-d e}\"`f;g`"),
+                Token::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_quotes_are_errors() {
+        for (input, message, column) in [
+            ("echo 'abc", "unterminated single quote", 6),
+            ("echo a\"b'c", "unterminated double quote", 7),
+            ("echo `ls", "unterminated backquote", 6),
+            ("echo x$(ls", "unterminated command substitution", 7),
+            ("echo ${x", "unterminated parameter expansion", 6),
+        ] {
+            let Err(Error::Lex(err)) = lex(input, "test", Options::default()) else {
+                panic!("no error for {input}");
+            };
+            assert_eq!(err.message, message, "{input}");
+            assert_eq!((err.span.line, err.span.column), (1, column), "{input}");
+        }
+    }
+
+    #[test]
+    fn comments_only_start_at_the_start_of_a_word() {
+        assert_eq!(
+            tokens("echo a#b # c 'd"),
+            [
+                text("echo"),
+                Token::Space,
+                text("a#b"),
+                Token::Space,
+                Token::Eof
+            ]
+        );
+        assert_eq!(
+            tokens("#c\necho;#d"),
+            [Token::Newline, text("echo"), Token::Semi, Token::Eof]
+        );
+    }
+
+    #[test]
+    fn here_documents() {
+        let input = "cat <<-'E O'F <<X\n\tbody $x\n\tE OF\n$y\nX\necho\n";
+        let tokens = lex(input, "test", Options::default()).unwrap();
+        let Token::HereDoc(first) = &tokens[2].0 else {
+            panic!("not a here-document: {tokens:?}");
+        };
+        assert_eq!(first.delimiter, "E OF");
+        assert!(first.quoted);
+        assert!(first.strip_tabs);
+        assert_eq!(first.body, "body $x\n");
+        let span = first.body_span;
+        assert_eq!(&input[span.start..span.end], "\tbody $x\n");
+        assert_eq!((span.line, span.column), (2, 1));
+
+        let Token::HereDoc(second) = &tokens[4].0 else {
+            panic!("not a here-document: {tokens:?}");
+        };
+        assert_eq!(second.delimiter, "X");
+        assert!(!second.quoted);
+        assert!(!second.strip_tabs);
+        assert_eq!(second.body, "$y\n");
+        assert_eq!(second.body_span.line, 4);
+
+        assert_eq!(
+            tokens[5..]
+                .iter()
+                .map(|(token, _)| token)
+                .collect::<Vec<_>>(),
+            [&Token::Newline, &text("echo"), &Token::Newline, &Token::Eof]
+        );
+    }
+
+    #[test]
+    fn missing_here_document_delimiter() {
+        let Err(Error::Lex(err)) = lex("cat <<", "test", Options::default()) else {
+            panic!("no error");
+        };
+        assert_eq!(err.message, "missing here-document delimiter");
+    }
+
+    #[test]
+    fn line_continuations_need_more_input() {
+        assert!(needs_more_input("echo hi \\\n"));
+        assert!(needs_more_input("echo hi\\\n"));
+        assert!(needs_more_input("echo 'a b'\\\n"));
+        assert!(!needs_more_input("echo hi\n"));
+        assert!(!needs_more_input("echo hi\\\\\n"));
+        assert!(!needs_more_input("echo hi # note \\\n"));
+        assert!(!needs_more_input("echo hi \\\nthere\n"));
+    }
 
-#[must_use] pub fn lex(input: &str, _options: Options) -> (i32, Tokens) {
-    let tokens = Token::lexer(input).collect();
-    (0, tokens)
+    #[test]
+    fn here_documents_need_more_input() {
+        assert!(needs_more_input("cat <<EOF\n"));
+        assert!(needs_more_input("cat <<EOF\nbody\n"));
+        assert!(!needs_more_input("cat <<EOF\nbody\nEOF\n"));
+    }
 }
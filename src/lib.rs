@@ -0,0 +1,37 @@
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+#![warn(clippy::nursery)]
+#![warn(clippy::cargo)]
+#![warn(clippy::suspicious)]
+#![allow(clippy::cargo_common_metadata)]
+
+/// Evaluates arithmetic expressions.
+pub mod arith;
+/// Brace expansion of words.
+pub mod brace;
+/// Commands built into the shell.
+mod builtins;
+/// Errors of the shell and the exit statuses they result in.
+pub mod error;
+/// Expands words into the fields passed to commands.
+mod expand;
+/// Pathname expansion.
+pub mod glob;
+/// Lex's the input string into a vector of tokens.
+pub mod lex;
+/// Implements a Peekable-like trait so you can peek multiple items ahead.
+pub mod lookahead;
+/// Parses the vector of tokens into a command tree.
+pub mod parse;
+/// Shell pattern matching.
+pub mod pattern;
+/// Runs the shell.
+pub mod run;
+/// Tilde expansion of home directories.
+pub mod tilde;
+/// Options for the shell.
+pub mod options;
+/// Shell variables.
+pub mod vars;
+
+pub use options::Options;
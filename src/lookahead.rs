@@ -4,8 +4,6 @@
 #![warn(clippy::cargo)]
 #![warn(clippy::suspicious)]
 
-use std::array::IntoIter;
-
 /// The Cursor-based type used internally to implement a type that can look ahead and behind without
 /// consuming the item.
 #[derive(Debug, Clone)]
@@ -33,17 +31,17 @@ impl<T: Sized + Clone> Lookahead<T> {
     }
 
     /// Moves the cursor forward by `n` items.
-    pub fn forward(&mut self, n: usize) {
+    pub const fn forward(&mut self, n: usize) {
         self.peek_cursor += n;
     }
 
     /// Moves the cursor backward by `n` items.
-    pub fn backward(&mut self, n: usize) {
+    pub const fn backward(&mut self, n: usize) {
         self.peek_cursor -= n;
     }
 
     /// Moves the peek cursor to the real cursor.
-    pub fn reset_cursor(&mut self) {
+    pub const fn reset_cursor(&mut self) {
         self.peek_cursor = self.real_cursor;
     }
 }
@@ -3,88 +3,149 @@
 #![warn(clippy::nursery)]
 #![warn(clippy::cargo)]
 #![warn(clippy::suspicious)]
+#![allow(clippy::cargo_common_metadata)]
 
 use log::info;
+use qiish::error::Error;
+use qiish::run::{self, Interpreter};
+use qiish::{lex, parse, Options};
+use std::io::Read;
 use std::process::exit;
-use crate::options::Options;
 
-/// Lex's the input string into a vector of tokens.
-pub(crate) mod lex;
-/// Implements a Peekable-like trait so you can peek multiple items ahead.
-pub(crate) mod lookahead;
-/// Parses the vector of tokens into a vector of parsed tokens.
-pub(crate) mod parse;
-/// Runs the shell.
-pub(crate) mod run;
-/// Options for the shell.
-pub(crate) mod options;
+/// Where the commands to run are read from.
+enum Source {
+    /// A script file.
+    File(String),
+    /// The command string of `-c`.
+    Command(String),
+    /// Standard input.
+    Stdin,
+}
+
+/// What the shell was asked to do on its command line.
+struct Invocation {
+    options: Options,
+    source: Source,
+    /// `$0`.
+    name: String,
+    /// The positional parameters.
+    args: Vec<String>,
+}
 
 fn main() {
     env_logger::init();
 
-    let args = std::env::args();
-    let args = args.collect::<Vec<String>>();
-    let args = args.iter().skip(1).collect::<Vec<&String>>().clone();
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let status = match parse_args(args) {
+        Ok(invocation) => run(invocation),
+        Err(err) => err.report(),
+    };
+    exit(status);
+}
 
-    // Check if args has exactly 1 or 2 elements
-    match args.len() {
-        1 => {
-            exit(run(args[0], Options::default()));
-        }
+/// Parses `qiish [-hsvV] [script [args...]]` or `qiish [-hvV] -c commands [name [args...]]`.
+///
+/// Without a script, or with `-s`, commands are read from stdin and every operand is a
+/// positional parameter.
+fn parse_args(args: Vec<String>) -> Result<Invocation, Error> {
+    let mut options = Options::default();
+    let mut command = false;
+    let mut stdin = false;
 
-        2 => {
-            let options = match_options(args[0]);
-            let options: Options = parse_options(options);
-            exit(run(args[1], options));
+    let mut args = args.into_iter().peekable();
+    while let Some(arg) = args.next_if(|arg| arg.starts_with('-') && arg != "-") {
+        if arg == "--" {
+            break;
         }
-
-        _ => {
-            exit(1);
+        for c in arg.chars().skip(1) {
+            match c {
+                'c' => command = true,
+                's' => stdin = true,
+                'h' => options.help = true,
+                'v' => options.verbose = true,
+                'V' => options.version = true,
+                _ => return Err(Error::Usage(format!("-{c}: invalid option"))),
+            }
         }
     }
-}
 
-fn parse_options(options: Vec<char>) -> Options {
-    let mut ret = Options {
-        help: false,
-        version: false,
-        verbose: false,
+    let source = if command {
+        let command = args
+            .next()
+            .ok_or_else(|| Error::Usage("-c: option requires an argument".to_string()))?;
+        Source::Command(command)
+    } else if stdin {
+        Source::Stdin
+    } else {
+        args.next().map_or(Source::Stdin, Source::File)
     };
-    if options.contains(&'v') {
-        ret.verbose = true;
-    }
-    if options.contains(&'V') {
-        ret.version = true;
-    }
-    if options.contains(&'h') {
-        ret.help = true;
-    }
-    ret
+    let name = match &source {
+        Source::File(path) => path.clone(),
+        Source::Command(_) => args.next().unwrap_or_else(|| "qiish".to_string()),
+        Source::Stdin => "qiish".to_string(),
+    };
+
+    Ok(Invocation {
+        options,
+        source,
+        name,
+        args: args.collect(),
+    })
 }
 
-fn run(infile: &str, options: Options) -> i32 {
-    let tokens = match lex::lex(infile, options) {
-        (0, tok) => tok,
-        (exit_, _) => return exit_,
-    };
+/// Reads and runs the commands, returning the exit status of the shell.
+fn run(invocation: Invocation) -> i32 {
+    let Invocation {
+        options,
+        source,
+        name,
+        args,
+    } = invocation;
 
-    info!("{:?}", tokens);
+    if options.help {
+        println!("Usage: qiish [-hsvV] [script [args...]]");
+        println!("       qiish [-hvV] -c commands [name [args...]]");
+        return 0;
+    }
+    if options.version {
+        println!("qiish v0.1.0");
+        return 0;
+    }
 
-    let parsed_tokens = match parse::parse(tokens, options) {
-        (0, tok) => tok,
-        (exit_, _) => return exit_,
+    // Errors point into the input by this name.
+    let (input, input_name) = match source {
+        Source::File(path) => match std::fs::read_to_string(&path) {
+            Ok(input) => (input, path),
+            Err(source) => {
+                return Error::Exec {
+                    command: path,
+                    source,
+                }
+                .report()
+            }
+        },
+        Source::Command(command) => (command, "-c".to_string()),
+        Source::Stdin => {
+            let mut input = String::new();
+            if let Err(source) = std::io::stdin().read_to_string(&mut input) {
+                return Error::Io {
+                    context: "cannot read stdin",
+                    source,
+                }
+                .report();
+            }
+            (input, "stdin".to_string())
+        }
     };
-    0
-}
 
-fn match_options(options: &str) -> Vec<char> {
-    let mut options = options.chars();
-    options.next();
+    let mut interpreter = Interpreter::new(options);
+    interpreter.set_args(name, args);
 
-    let mut ret = vec![];
-    for option in options {
-        ret.push(option);
-    }
-    ret
+    lex::lex(&input, &input_name, options)
+        .and_then(|tokens| {
+            info!("{:?}", tokens);
+            parse::parse(tokens, &input, &input_name, options)
+        })
+        .and_then(|script| run::run(&script, &mut interpreter))
+        .unwrap_or_else(|err| err.report())
 }
-
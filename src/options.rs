@@ -1,6 +1,25 @@
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Options {
     pub help: bool,
     pub version: bool,
     pub verbose: bool,
-}
\ No newline at end of file
+    /// Glob patterns that match no file expand to nothing, rather than to themselves.
+    pub nullglob: bool,
+    /// Glob patterns that match no file are an error.
+    pub failglob: bool,
+    /// The interactive shell does not exit on end of file, as when Ctrl-D is pressed.
+    pub ignoreeof: bool,
+}
+
+impl Options {
+    /// The letters of the options that are set, as found in `$-`.
+    #[must_use]
+    pub fn flags(&self) -> String {
+        let mut flags = String::new();
+        if self.verbose {
+            flags.push('v');
+        }
+        flags
+    }
+}
@@ -5,257 +5,1329 @@
 #![warn(clippy::suspicious)]
 
 use crate::{
-    lex::{Token, Tokens},
+    brace,
+    error::{Error, SyntaxError},
+    lex::{
+        arithmetic_expression, braced_len, closing_backquote, closing_quote, lex_at, nested_len,
+        paren_len, HereDoc, Span, Token, Tokens,
+    },
     lookahead::Lookahead,
+    vars::{is_name, is_special_parameter},
     Options,
 };
 use log::info;
+use std::os::fd::RawFd;
 
-#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
-pub enum ParsedToken {
-    Command(String, Vec<String>),
-    Redir(String),
-    Pipe(usize, usize),
-    HereDoc(usize, String),
-    HereString(usize, String),
-    RedirClobber(String),
-    RedirAppend(String),
-    RedirInput(String),
-    AndAnd(usize, usize),
-    OrOr(usize, usize),
-    Eof,
-    Error(String),
-    String(String),
-    Delimiter,
-}
-
-pub type ParsedTokens = Vec<ParsedToken>;
-pub type TokenStream = Lookahead<Token>;
-
-#[allow(clippy::too_many_lines)]
-pub fn parse(mut in_: Tokens, options: Options) -> (i32, ParsedTokens) {
-    let mut parsed_tokens: Vec<ParsedToken> = vec![];
-    // Temporary until EOF token is implemented
-    // TODO: REMOVE THIS
-    in_.push(Token::Eof);
-    let mut token_stream: TokenStream = <TokenStream>::from(in_);
+/// A complete script: every list found in the input, one per line, in order.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Script {
+    pub lists: Vec<List>,
+    pub span: Span,
+}
+
+/// A sequence of and-or chains separated by `;` or `&`, executed one after another.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct List {
+    pub and_ors: Vec<AndOr>,
+    pub span: Span,
+}
+
+/// Pipelines chained together with `&&` and `||`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+    /// Whether the chain is followed by `&`, and so runs in the background.
+    pub asynchronous: bool,
+    pub span: Span,
+}
+
+/// The operator joining two pipelines of an [`AndOr`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Connector {
+    /// `&&`: run the right side only if the left side succeeded.
+    AndAnd,
+    /// `||`: run the right side only if the left side failed.
+    OrOr,
+}
+
+/// Commands joined by `|`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+    pub span: Span,
+}
+
+/// A single command of a [`Pipeline`].
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Command {
+    Simple(SimpleCommand),
+    /// `(( expr ))`: evaluates the arithmetic expression, succeeding if it is not zero.
+    Arithmetic(Word),
+}
+
+/// A command name with its arguments and the redirections that apply to it.
+///
+/// Assignments written before the command name only apply to that command. Without a
+/// command name, they set shell variables.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<Word>,
+    pub redirections: Vec<Redirection>,
+    pub span: Span,
+}
+
+/// A `NAME=value` assignment.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+    pub span: Span,
+}
+
+/// A single shell word, made of adjacent literal and quoted parts.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+    pub span: Span,
+}
+
+/// A piece of a [`Word`].
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct WordPart {
+    pub kind: WordPartKind,
+    /// Where the part appears in the input. Parts of here-document bodies and of
+    /// `` `...` `` substitutions may be slightly off, as tabs and backslashes are removed
+    /// from those before they are parsed.
+    pub span: Span,
+}
+
+/// What a [`WordPart`] is.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum WordPartKind {
+    /// Unquoted text.
+    Literal(String),
+    /// The contents of a `'...'` string, or a backslash-escaped character.
+    SingleQuoted(String),
+    /// The contents of a `"..."` string. Only [`WordPartKind::Literal`] and expansions
+    /// appear inside.
+    DoubleQuoted(Vec<WordPart>),
+    /// A `$NAME` or `${NAME}` parameter expansion.
+    Parameter(Parameter),
+    /// A `$(...)` or `` `...` `` command substitution.
+    CommandSubstitution(Script),
+    /// The login name of a `~name` prefix, which is empty for the current user's home
+    /// directory, `+` for `$PWD` and `-` for `$OLDPWD`.
+    Tilde(String),
+    /// The expression of a `$((...))` arithmetic expansion, which is expanded like a
+    /// here-document before being evaluated.
+    Arithmetic(Vec<WordPart>),
+}
+
+/// A parameter expansion.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Parameter {
+    pub name: String,
+    pub op: Option<ParameterOp>,
+    pub span: Span,
+}
+
+/// The operator of a `${...}` parameter expansion.
+///
+/// With `colon`, as in `${name:-word}`, a parameter set to the empty string is treated
+/// like an unset one.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ParameterOp {
+    /// `${#name}`: the length of the value.
+    Length,
+    /// `${name:-word}`: the word if the parameter is unset.
+    Default { colon: bool, word: Word },
+    /// `${name:=word}`: the word if the parameter is unset, also assigning it.
+    Assign { colon: bool, word: Word },
+    /// `${name:?word}`: an error, with the word as message, if the parameter is unset.
+    Error { colon: bool, word: Word },
+    /// `${name:+word}`: the word if the parameter is set, nothing otherwise.
+    Alternative { colon: bool, word: Word },
+    /// `${name#pattern}` and `${name##pattern}`: the value without the shortest or longest
+    /// prefix matching the pattern.
+    RemovePrefix { longest: bool, pattern: Word },
+    /// `${name%pattern}` and `${name%%pattern}`: the value without the shortest or longest
+    /// suffix matching the pattern.
+    RemoveSuffix { longest: bool, pattern: Word },
+    /// `${name/pattern/replacement}` and its `//`, `/#` and `/%` forms.
+    Replace {
+        mode: ReplaceMode,
+        pattern: Word,
+        replacement: Word,
+    },
+    /// `${name:offset}` and `${name:offset:length}`: a slice of the value, in characters.
+    Substring { offset: Word, length: Option<Word> },
+    /// `${name^pattern}`, `${name^^pattern}`, `${name,pattern}` and `${name,,pattern}`: the
+    /// value with the first or all characters matching the pattern changed to upper or lower
+    /// case. An empty pattern matches any character.
+    Case {
+        upper: bool,
+        all: bool,
+        pattern: Word,
+    },
+}
+
+/// Which matches of the pattern a [`ParameterOp::Replace`] replaces.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ReplaceMode {
+    /// `/`: the first match.
+    First,
+    /// `//`: every match.
+    All,
+    /// `/#`: a match at the start of the value.
+    Prefix,
+    /// `/%`: a match at the end of the value.
+    Suffix,
+}
+
+/// A redirection attached to a [`SimpleCommand`].
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Redirection {
+    pub kind: RedirectionKind,
+    /// The file descriptor written before the operator, as in `2>`. Without one, input
+    /// redirections apply to stdin and output redirections to stdout.
+    pub fd: Option<RawFd>,
+    pub target: Word,
+    pub span: Span,
+}
+
+/// The operator of a [`Redirection`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum RedirectionKind {
+    /// `>`
+    Output,
+    /// `>|`
+    Clobber,
+    /// `>>`
+    Append,
+    /// `<`
+    Input,
+    /// `<<` or `<<-`, the target being the body of the here-document.
+    HereDoc,
+    /// `<<<`, the target being the string fed to stdin, without its trailing newline.
+    HereString,
+    /// `<>`, opening the target for both reading and writing.
+    ReadWrite,
+    /// `<&`, the target being a file descriptor to duplicate, or `-` to close it.
+    DupInput,
+    /// `>&`, the target being a file descriptor to duplicate, or `-` to close it.
+    DupOutput,
+    /// `&>`, redirecting both stdout and stderr.
+    All,
+    /// `&>>`, appending both stdout and stderr.
+    AllAppend,
+}
 
+pub type TokenStream = Lookahead<(Token, Span)>;
+
+/// Parses the tokens into a [`Script`].
+///
+/// Syntax errors point into `source`, which is called `name` in messages.
+///
+/// # Errors
+///
+/// Returns [`Error::Syntax`] if the tokens do not form a valid script.
+pub fn parse(in_: Tokens, source: &str, name: &str, options: Options) -> Result<Script, Error> {
     if options.verbose {
         info!("Parsing tokens...");
     }
 
-    let iter = token_stream.clone();
+    let mut parser = Parser::new(in_);
 
-    for tok in iter {
-        info!("Parsing token: {:?}", tok);
-        match tok {
-            Token::AndAnd => {
-                let left = parsed_tokens.len() - 1;
-                let right = parsed_tokens.len() + 1;
-                parsed_tokens.push(ParsedToken::AndAnd(left, right));
-            }
-            // Currently, EoF token is not implemented
-            Token::Eof => parsed_tokens.push(ParsedToken::Eof),
-            Token::Error => {
-                parsed_tokens.push(ParsedToken::Error("Error parsing token".to_string()));
-            }
-            Token::HereDoc => {
-                let mut here_doc = String::new();
-                let mut end_token = String::new();
-                let mut end_token_found = false;
-                let mut end_token_len = 0;
-
-                token_stream.forward(1);
-                if let Some(Token::Text(s)) = token_stream.current() {
-                    end_token = s.clone();
-                    end_token_len = s.len();
-                }
+    let script = parser
+        .script()
+        .map_err(|err| Error::Syntax(err.in_source(name, source)))?;
+    if options.verbose {
+        info!("Finished parsing tokens.");
+        info!("Parsed script: {:?}", script);
+    }
+    Ok(script)
+}
 
-                token_stream.forward(1);
-                while let Some(Token::Text(s)) = token_stream.current() {
-                    if s == end_token {
-                        end_token_found = true;
-                        break;
-                    }
-                    here_doc.push_str(&s);
-                    token_stream.forward(1);
-                }
+/// Recursive descent parser over a [`TokenStream`].
+struct Parser {
+    tokens: TokenStream,
+    /// The span of the last token consumed other than a space, where the node being parsed
+    /// ends so far.
+    last: Span,
+}
 
-                if end_token_found {
-                    token_stream.forward(end_token_len);
-                    parsed_tokens.push(ParsedToken::HereDoc(parsed_tokens.len(), here_doc));
-                } else {
-                    parsed_tokens.push(ParsedToken::Error(
-                        "HereDoc: End token not found".to_string(),
-                    ));
-                }
-            }
-            Token::HereString => {
-                let here_string;
+impl Parser {
+    fn new(tokens: Tokens) -> Self {
+        Self {
+            tokens: TokenStream::from(tokens),
+            last: Span::default(),
+        }
+    }
 
-                token_stream.forward(1);
-                if let Some(Token::Text(s)) = token_stream.current() {
-                    here_string = s.clone();
-                } else {
-                    parsed_tokens.push(ParsedToken::Error(
-                        "HereString: No string found".to_string(),
-                    ));
-                    continue;
-                }
+    /// Returns the next token without consuming it, treating [`Token::Eof`] as the end.
+    fn peek(&mut self) -> Option<Token> {
+        match self.tokens.current() {
+            Some((Token::Eof, _)) | None => None,
+            Some((tok, _)) => Some(tok),
+        }
+    }
+
+    /// Returns the span of the next token, or of the end of the input.
+    fn peek_span(&mut self) -> Span {
+        self.tokens.current().map_or(
+            Span {
+                start: self.last.end,
+                ..self.last
+            },
+            |(_, span)| span,
+        )
+    }
 
-                parsed_tokens.push(ParsedToken::HereString(parsed_tokens.len(), here_string));
+    /// Consumes the next token.
+    fn advance(&mut self) {
+        if let Some((token, span)) = self.tokens.next() {
+            if token != Token::Space {
+                self.last = span;
             }
-            Token::OrOr => {
-                let left = parsed_tokens.len() - 1;
-                let right = parsed_tokens.len() + 1;
-                parsed_tokens.push(ParsedToken::OrOr(left, right));
+        }
+    }
+
+    /// Returns the token after the next one without consuming anything.
+    fn peek_second(&mut self) -> Option<Token> {
+        self.tokens.forward(1);
+        let token = self.tokens.current();
+        self.tokens.backward(1);
+        token.map(|(token, _)| token)
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek() == Some(Token::Space) {
+            self.advance();
+        }
+    }
+
+    /// Skips spaces and newlines, which may follow operators such as `&&` and `|`.
+    fn skip_linebreaks(&mut self) {
+        while matches!(self.peek(), Some(Token::Space | Token::Newline)) {
+            self.advance();
+        }
+    }
+
+    fn error(&mut self, message: String) -> SyntaxError {
+        SyntaxError {
+            message,
+            span: self.peek_span(),
+            ..SyntaxError::default()
+        }
+    }
+
+    /// An error about the next token, which cannot appear where it is.
+    fn unexpected(&mut self) -> SyntaxError {
+        let message = self.peek().map_or_else(
+            || "unexpected end of file".to_string(),
+            |tok| format!("unexpected '{tok}'"),
+        );
+        self.error(message)
+    }
+
+    fn script(&mut self) -> Result<Script, SyntaxError> {
+        let mut script = Script::default();
+
+        loop {
+            self.skip_linebreaks();
+            if self.peek().is_none() {
+                return Ok(script);
             }
-            Token::Pipe => {
-                let left = parsed_tokens.len() - 1;
-                let right = parsed_tokens.len() + 1;
-                parsed_tokens.push(ParsedToken::Pipe(left, right));
+            let list = self.list()?;
+            script.span = if script.lists.is_empty() {
+                list.span
+            } else {
+                script.span.to(list.span)
+            };
+            script.lists.push(list);
+            match self.peek() {
+                None | Some(Token::Newline) => (),
+                _ => return Err(self.unexpected()),
             }
-            Token::Redir => {
-                token_stream.forward(1);
-                let next = token_stream.current();
-                if let Some(Token::Text(s)) = next {
-                    parsed_tokens.push(ParsedToken::Redir(s.to_string()));
-                } else {
-                    parsed_tokens.push(ParsedToken::Error(
-                        "Expected a filename after redirection".to_string(),
-                    ));
+        }
+    }
+
+    /// Parses and-or chains up to the end of the line. The last one may or may not be
+    /// followed by a `;` or `&`.
+    fn list(&mut self) -> Result<List, SyntaxError> {
+        let start = self.peek_span();
+        let mut and_ors = vec![];
+
+        loop {
+            let mut and_or = self.and_or()?;
+            self.skip_spaces();
+            match self.peek() {
+                Some(Token::Semi) => (),
+                Some(Token::Amp) => and_or.asynchronous = true,
+                _ => {
+                    and_ors.push(and_or);
+                    break;
                 }
             }
-            Token::RedirAppend => {
-                token_stream.forward(1);
-                let next = token_stream.current();
-                if let Some(Token::Text(s)) = next {
-                    parsed_tokens.push(ParsedToken::RedirAppend(s.to_string()));
-                } else {
-                    parsed_tokens.push(ParsedToken::Error(
-                        "Expected a filename after redirection".to_string(),
-                    ));
-                }
+            self.advance();
+            and_ors.push(and_or);
+
+            self.skip_spaces();
+            if matches!(self.peek(), None | Some(Token::Newline)) {
+                break;
             }
-            Token::RedirClobber => {
-                token_stream.forward(1);
-                let next = token_stream.current();
-                if let Some(Token::Text(s)) = next {
-                    parsed_tokens.push(ParsedToken::RedirClobber(s.to_string()));
-                } else {
-                    parsed_tokens.push(ParsedToken::Error(
-                        "Expected a filename after redirection".to_string(),
-                    ));
-                }
+        }
+
+        Ok(List {
+            and_ors,
+            span: start.to(self.last),
+        })
+    }
+
+    fn and_or(&mut self) -> Result<AndOr, SyntaxError> {
+        let first = self.pipeline()?;
+        let mut rest = vec![];
+
+        loop {
+            self.skip_spaces();
+            let connector = match self.peek() {
+                Some(Token::AndAnd) => Connector::AndAnd,
+                Some(Token::OrOr) => Connector::OrOr,
+                _ => break,
+            };
+            self.advance();
+            self.skip_linebreaks();
+            rest.push((connector, self.pipeline()?));
+        }
+
+        let end = rest
+            .last()
+            .map_or(first.span, |(_, pipeline)| pipeline.span);
+        Ok(AndOr {
+            span: first.span.to(end),
+            first,
+            rest,
+            asynchronous: false,
+        })
+    }
+
+    fn pipeline(&mut self) -> Result<Pipeline, SyntaxError> {
+        let start = self.peek_span();
+        let mut commands = vec![self.command()?];
+
+        loop {
+            self.skip_spaces();
+            if self.peek() != Some(Token::Pipe) {
+                break;
             }
-            Token::RedirInput => {
-                token_stream.forward(1);
-                let next = token_stream.current();
-                if let Some(Token::Text(s)) = next {
-                    parsed_tokens.push(ParsedToken::RedirInput(s.to_string()));
-                } else {
-                    parsed_tokens.push(ParsedToken::Error(
-                        "Expected a filename after redirection".to_string(),
-                    ));
+            self.advance();
+            self.skip_linebreaks();
+            commands.push(self.command()?);
+        }
+
+        Ok(Pipeline {
+            commands,
+            span: start.to(self.last),
+        })
+    }
+
+    fn command(&mut self) -> Result<Command, SyntaxError> {
+        if let Some(Token::Arithmetic(expr)) = self.peek() {
+            // The expression is on the same line as the `((` before it.
+            let span = self.peek_span();
+            let span = Span {
+                start: span.start + 2,
+                end: span.end - 2,
+                column: span.column + 2,
+                ..span
+            };
+            let parts = parse_parts(&expr, Quoting::HereDoc, span)?;
+            self.advance();
+            return Ok(Command::Arithmetic(Word { parts, span }));
+        }
+
+        self.simple_command().map(Command::Simple)
+    }
+
+    fn simple_command(&mut self) -> Result<SimpleCommand, SyntaxError> {
+        self.skip_spaces();
+        let mut command = SimpleCommand {
+            span: self.peek_span(),
+            ..SimpleCommand::default()
+        };
+
+        loop {
+            self.skip_spaces();
+            let start = self.peek_span();
+            let fd = self.io_number();
+            let kind = match self.peek() {
+                Some(Token::Redir) => RedirectionKind::Output,
+                Some(Token::RedirClobber) => RedirectionKind::Clobber,
+                Some(Token::RedirAppend) => RedirectionKind::Append,
+                Some(Token::RedirInput) => RedirectionKind::Input,
+                Some(Token::HereDoc(here_doc)) => {
+                    let span = self.peek_span();
+                    let target = here_doc_body(here_doc)?;
+                    self.advance();
+                    command.redirections.push(Redirection {
+                        kind: RedirectionKind::HereDoc,
+                        fd,
+                        target,
+                        span: start.to(span),
+                    });
+                    continue;
                 }
-            }
-            Token::DQString(s) => {
-                let mut s_chars = s.chars().peekable();
-                let mut new_s = String::new();
-
-                while let Some(c) = s_chars.next() {
-                    if c == '\\' {
-                        if let Some(next) = s_chars.peek() {
-                            match next {
-                                '$' => {
-                                    s_chars.next();
-                                    new_s.push('$');
-                                }
-                                '`' => {
-                                    s_chars.next();
-                                    new_s.push('`');
-                                }
-                                '"' => {
-                                    s_chars.next();
-                                    new_s.push('"');
-                                }
-                                '\\' => {
-                                    s_chars.next();
-                                    new_s.push('\\');
-                                }
-                                '!' => {
-                                    s_chars.next();
-                                    new_s.push_str("\\!");
-                                }
-                                _ => {
-                                    new_s.push('\\');
-                                    new_s.push(c);
+                Some(Token::HereString) => RedirectionKind::HereString,
+                Some(Token::RedirReadWrite) => RedirectionKind::ReadWrite,
+                Some(Token::RedirDupInput) => RedirectionKind::DupInput,
+                Some(Token::RedirDupOutput) => RedirectionKind::DupOutput,
+                Some(Token::RedirAll) => RedirectionKind::All,
+                Some(Token::RedirAllAppend) => RedirectionKind::AllAppend,
+                _ => match self.word()? {
+                    Some(word) => {
+                        let word = if command.words.is_empty() {
+                            match assignment(word) {
+                                Ok(assignment) => {
+                                    command.assignments.push(assignment);
+                                    continue;
                                 }
+                                Err(word) => word,
                             }
-                        }
-                    }
-                    if c == '`' {
-                        new_s.push_str("\\`");
-                    } else if c == '$' {
-                        if let Some(next) = s_chars.peek() {
-                            if *next == '(' {
-                                s_chars.next();
-                                new_s.push_str("\\$(");
-                            } else if *next == '{' {
-                                s_chars.next();
-                                new_s.push_str("\\${");
-                            } else {
-                                new_s.push(c);
-                            }
-                        }
+                        } else {
+                            word
+                        };
+                        command
+                            .words
+                            .extend(brace::expand(word).into_iter().map(|word| Word {
+                                parts: tilde_prefixes(word.parts, false),
+                                ..word
+                            }));
+                        continue;
                     }
+                    None => break,
+                },
+            };
+            self.advance();
+            self.skip_spaces();
+            let Some(target) = self.word()? else {
+                let found = self
+                    .peek()
+                    .map_or_else(|| "end of file".to_string(), |tok| format!("'{tok}'"));
+                return Err(self.error(format!("expected a word after redirection, found {found}")));
+            };
+            command.redirections.push(Redirection {
+                kind,
+                fd,
+                span: start.to(target.span),
+                target: Word {
+                    parts: tilde_prefixes(target.parts, false),
+                    ..target
+                },
+            });
+        }
+
+        if command.assignments.is_empty()
+            && command.words.is_empty()
+            && command.redirections.is_empty()
+        {
+            return Err(self.unexpected());
+        }
+
+        command.span = command.span.to(self.last);
+        Ok(command)
+    }
+
+    /// Consumes a word of digits that comes right before a redirection operator that takes
+    /// a file descriptor, as in `2>`, and returns the file descriptor.
+    fn io_number(&mut self) -> Option<RawFd> {
+        let Some(Token::Text(s)) = self.peek() else {
+            return None;
+        };
+        let takes_fd = matches!(
+            self.peek_second(),
+            Some(
+                Token::Redir
+                    | Token::RedirClobber
+                    | Token::RedirAppend
+                    | Token::RedirInput
+                    | Token::HereDoc(_)
+                    | Token::HereString
+                    | Token::RedirReadWrite
+                    | Token::RedirDupInput
+                    | Token::RedirDupOutput
+            )
+        );
+        if !takes_fd || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let fd = s.parse().ok()?;
+        self.advance();
+        Some(fd)
+    }
+
+    /// Parses the next word token, if there is one, into a [`Word`].
+    fn word(&mut self) -> Result<Option<Word>, SyntaxError> {
+        let Some(Token::Text(s)) = self.peek() else {
+            return Ok(None);
+        };
+        let span = self.peek_span();
+        let parts = parse_parts(&s, Quoting::Unquoted, span)?;
+        self.advance();
+        Ok(Some(Word { parts, span }))
+    }
+}
+
+/// Splits a word of the form `NAME=value` into an [`Assignment`], or gives it back if it
+/// is not one.
+fn assignment(mut word: Word) -> Result<Assignment, Word> {
+    let Some(WordPart {
+        kind: WordPartKind::Literal(first),
+        span,
+    }) = word.parts.first_mut()
+    else {
+        return Err(word);
+    };
+    let Some((name, value)) = first.split_once('=') else {
+        return Err(word);
+    };
+    if !is_name(name) {
+        return Err(word);
+    }
+
+    let start = name.len() + 1;
+    let value_span = span.slice(first, start..start).to(word.span);
+    let name = name.to_string();
+    let value = value.to_string();
+    if value.is_empty() {
+        word.parts.remove(0);
+    } else {
+        *span = span.slice(first, start..start).to(*span);
+        *first = value;
+    }
+    Ok(Assignment {
+        name,
+        value: Word {
+            parts: tilde_prefixes(word.parts, true),
+            span: value_span,
+        },
+        span: word.span,
+    })
+}
+
+/// Turns the unquoted `~` prefixes of a word into [`WordPartKind::Tilde`] parts: the one at
+/// the start of the word and, in assignments, those following a `:`.
+///
+/// A prefix runs up to the next `/`, or `:` in assignments. It is left alone if any of it
+/// is quoted or expanded.
+fn tilde_prefixes(parts: Vec<WordPart>, assignment: bool) -> Vec<WordPart> {
+    let count = parts.len();
+    let mut new_parts = Vec::with_capacity(count);
+
+    for (i, part) in parts.into_iter().enumerate() {
+        let WordPart {
+            kind: WordPartKind::Literal(text),
+            span,
+        } = part
+        else {
+            new_parts.push(part);
+            continue;
+        };
+
+        let mut literal = String::new();
+        let mut literal_start = 0;
+        let mut rest = 0;
+        // Whether a prefix may start at `rest`.
+        let mut may_start = i == 0;
+        loop {
+            if may_start && text[rest..].starts_with('~') {
+                let end = text[rest..].find(|c| c == '/' || (assignment && c == ':'));
+                if end.is_some() || i + 1 == count {
+                    let end = rest + end.unwrap_or(text.len() - rest);
+                    flush_literal(
+                        &mut literal,
+                        &mut new_parts,
+                        span.slice(&text, literal_start..rest),
+                    );
+                    new_parts.push(WordPart {
+                        kind: WordPartKind::Tilde(text[rest + 1..end].to_string()),
+                        span: span.slice(&text, rest..end),
+                    });
+                    rest = end;
+                    literal_start = end;
+                }
+            }
+            if !assignment {
+                break;
+            }
+            let Some(colon) = text[rest..].find(':') else {
+                break;
+            };
+            literal.push_str(&text[rest..=rest + colon]);
+            rest += colon + 1;
+            may_start = true;
+        }
+        literal.push_str(&text[rest..]);
+        let literal_span = span.slice(&text, literal_start..literal_start).to(span);
+        flush_literal(&mut literal, &mut new_parts, literal_span);
+    }
+
+    new_parts
+}
+
+/// Turns a lexed here-document into the [`Word`] fed to the command's stdin.
+///
+/// The body of a here-document with a quoted delimiter is taken literally. Otherwise it is
+/// treated like a double-quoted string in which `"` is not special.
+fn here_doc_body(here_doc: HereDoc) -> Result<Word, SyntaxError> {
+    let span = here_doc.body_span;
+    let kind = if here_doc.quoted {
+        WordPartKind::SingleQuoted(here_doc.body)
+    } else {
+        WordPartKind::DoubleQuoted(parse_parts(&here_doc.body, Quoting::HereDoc, span)?)
+    };
+    Ok(Word {
+        parts: vec![WordPart { kind, span }],
+        span,
+    })
+}
 
-                    if let Some(ParsedToken::String(s)) = parsed_tokens.last_mut() {
-                        s.push(c);
+/// The context a piece of text appears in, which decides what backslashes escape.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Quoting {
+    /// A backslash quotes any character.
+    Unquoted,
+    /// A backslash only quotes `$`, `` ` ``, `"`, `\` and newline.
+    DoubleQuoted,
+    /// Like [`Quoting::DoubleQuoted`], except `"` is not special. Also used for arithmetic
+    /// expressions.
+    HereDoc,
+}
+
+impl Quoting {
+    const fn escapes(self, c: char) -> bool {
+        match self {
+            Self::Unquoted => true,
+            Self::DoubleQuoted => matches!(c, '$' | '`' | '"' | '\\' | '\n'),
+            Self::HereDoc => matches!(c, '$' | '`' | '\\' | '\n'),
+        }
+    }
+}
+
+fn syntax_error(message: String, span: Span) -> SyntaxError {
+    SyntaxError {
+        message,
+        span,
+        ..SyntaxError::default()
+    }
+}
+
+/// Splits text into literal parts, quoted parts and expansions, resolving backslash
+/// escapes. Quotes are only special in unquoted text.
+///
+/// `span` is where the text is in the input, from which the spans of the parts, of nested
+/// words and scripts, and of errors are worked out.
+fn parse_parts(s: &str, quoting: Quoting, span: Span) -> Result<Vec<WordPart>, SyntaxError> {
+    let mut parts = vec![];
+    let mut literal = String::new();
+    // Where the text gathered in `literal` starts.
+    let mut literal_start = 0;
+    let mut i = 0;
+
+    while let Some(c) = s[i..].chars().next() {
+        let start = i;
+        if literal.is_empty() {
+            literal_start = start;
+        }
+        i += c.len_utf8();
+        let kind = match c {
+            '\\' => match s[i..].chars().next() {
+                Some('\n') => {
+                    i += 1;
+                    None
+                }
+                Some(next) if quoting.escapes(next) => {
+                    i += next.len_utf8();
+                    if quoting == Quoting::Unquoted {
+                        Some(WordPartKind::SingleQuoted(next.to_string()))
                     } else {
-                        parsed_tokens.push(ParsedToken::String(c.to_string()));
+                        literal.push(next);
+                        None
                     }
                 }
-            }
-            Token::SQString(s) => {
-                if let Some(ParsedToken::String(s1)) = parsed_tokens.last_mut() {
-                    s1.push_str(&s);
-                } else {
-                    parsed_tokens.push(ParsedToken::String(s));
+                _ => {
+                    literal.push(c);
+                    None
                 }
+            },
+            '\'' if quoting == Quoting::Unquoted => {
+                let len = s[i..].find('\'').ok_or_else(|| {
+                    syntax_error(
+                        "unterminated single quote".to_string(),
+                        span.slice(s, start..i),
+                    )
+                })?;
+                let text = s[i..i + len].to_string();
+                i += len + 1;
+                Some(WordPartKind::SingleQuoted(text))
+            }
+            '"' if quoting == Quoting::Unquoted => {
+                let len = closing_quote(&s[i..]).ok_or_else(|| {
+                    syntax_error(
+                        "unterminated double quote".to_string(),
+                        span.slice(s, start..i),
+                    )
+                })?;
+                let inner = &s[i..i + len];
+                let parts = parse_parts(inner, Quoting::DoubleQuoted, span.slice(s, i..i + len))?;
+                i += len + 1;
+                Some(WordPartKind::DoubleQuoted(parts))
             }
-            Token::Text(s) => {
-                if let Some(ParsedToken::Command(s1, args)) = parsed_tokens.last_mut() {
-                    args.push(s.clone());
+            '`' => {
+                let len = closing_backquote(&s[i..]).ok_or_else(|| {
+                    syntax_error(
+                        "unterminated backquote".to_string(),
+                        span.slice(s, start..i),
+                    )
+                })?;
+                let script = parse_script(
+                    &unescape_backquoted(&s[i..i + len], quoting),
+                    span.slice(s, i..i + len),
+                )?;
+                i += len + 1;
+                Some(WordPartKind::CommandSubstitution(script))
+            }
+            '$' => {
+                let dollar = parse_dollar(&s[start..], span.slice(s, start..s.len()))?;
+                if let Some((kind, len)) = dollar {
+                    i = start + len;
+                    Some(kind)
                 } else {
-                    parsed_tokens.push(ParsedToken::Command(s.clone(), vec![]));
+                    literal.push(c);
+                    None
                 }
             }
-            Token::Space => {
-                #[allow(clippy::equatable_if_let)]
-                if let Some(ParsedToken::String(s)) = parsed_tokens.last_mut() {
-                    s.push(' ');
-                } else if let Some(ParsedToken::Delimiter) = parsed_tokens.last_mut() {
-                    continue;
-                } else if let Some(ParsedToken::Command(_, _)) = parsed_tokens.last_mut() {
-                    continue;
-                }
-                parsed_tokens.push(ParsedToken::Delimiter);
+            _ => {
+                literal.push(c);
+                None
             }
+        };
+
+        if let Some(kind) = kind {
+            flush_literal(
+                &mut literal,
+                &mut parts,
+                span.slice(s, literal_start..start),
+            );
+            parts.push(WordPart {
+                kind,
+                span: span.slice(s, start..i),
+            });
         }
     }
 
-    if options.verbose {
-        info!("Finished parsing tokens.");
-        info!(
-            "Parsed tokens: {:?}",
-            parsed_tokens
-                .clone()
-                .into_iter()
-                .map(|x| format!("{:?}", x))
-                .collect::<Vec<String>>()
+    flush_literal(&mut literal, &mut parts, span.slice(s, literal_start..i));
+    Ok(parts)
+}
+
+fn flush_literal(literal: &mut String, parts: &mut Vec<WordPart>, span: Span) {
+    if !literal.is_empty() {
+        parts.push(WordPart {
+            kind: WordPartKind::Literal(std::mem::take(literal)),
+            span,
+        });
+    }
+}
+
+/// Splits `s` at the first `separator` that is not quoted, escaped or inside a nested
+/// expansion.
+fn split_unquoted(s: &str, separator: char) -> (&str, Option<&str>) {
+    let mut i = 0;
+    while let Some(c) = s[i..].chars().next() {
+        if c == separator {
+            return (&s[..i], Some(&s[i + c.len_utf8()..]));
+        }
+        match nested_len(&s[i..]) {
+            Some(len) => i += len,
+            None => break,
+        }
+    }
+    (s, None)
+}
+
+/// Parses the expansion starting with the `$` at the start of `s`, returning it and the
+/// number of bytes it spans. Returns `None` if the `$` does not start an expansion and is to
+/// be taken literally.
+fn parse_dollar(s: &str, span: Span) -> Result<Option<(WordPartKind, usize)>, SyntaxError> {
+    let rest = &s[1..];
+    if let Some(expr) = arithmetic_expression(rest) {
+        let parts = parse_parts(expr, Quoting::HereDoc, span.slice(s, 3..3 + expr.len()))?;
+        return Ok(Some((WordPartKind::Arithmetic(parts), expr.len() + 5)));
+    }
+
+    if rest.starts_with('(') {
+        let len = 1 + paren_len(rest).ok_or_else(|| {
+            syntax_error(
+                "missing ')' in command substitution".to_string(),
+                span.slice(s, 0..2),
+            )
+        })?;
+        let script = parse_script(&s[2..len - 1], span.slice(s, 2..len - 1))?;
+        return Ok(Some((WordPartKind::CommandSubstitution(script), len)));
+    }
+
+    if rest.starts_with('{') {
+        let len = 1 + braced_len(rest).ok_or_else(|| {
+            syntax_error(
+                "missing '}' in parameter expansion".to_string(),
+                span.slice(s, 0..2),
+            )
+        })?;
+        let parameter = parse_braced(&s[..len], span.slice(s, 0..len))?;
+        return Ok(Some((WordPartKind::Parameter(parameter), len)));
+    }
+
+    let len = 1 + parameter_name_len(rest, false);
+    if len == 1 {
+        return Ok(None);
+    }
+    let kind = WordPartKind::Parameter(Parameter {
+        name: s[1..len].to_string(),
+        op: None,
+        span: span.slice(s, 0..len),
+    });
+    Ok(Some((kind, len)))
+}
+
+/// Returns the length of the parameter name at the start of `s`, or 0 if there is none.
+///
+/// The name is a variable name, the digits of a positional parameter or a single special
+/// parameter character. Outside of braces, positional parameters only take one digit, so
+/// `$10` is `$1` followed by `0`.
+fn parameter_name_len(s: &str, braced: bool) -> usize {
+    match s.chars().next() {
+        Some(c) if is_special_parameter(c) => 1,
+        Some('0'..='9') if !braced => 1,
+        Some('0'..='9') => s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()),
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => s
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(s.len()),
+        _ => 0,
+    }
+}
+
+/// Parses the command of a command substitution, which is at `span` in the input.
+fn parse_script(s: &str, span: Span) -> Result<Script, SyntaxError> {
+    let tokens = lex_at(s, span)?;
+    Parser::new(tokens).script()
+}
+
+/// Removes the backslashes that quote `$`, `` ` `` and `\` inside a `` `...` `` command
+/// substitution, as well as `"` if the substitution is double-quoted.
+fn unescape_backquoted(s: &str, quoting: Quoting) -> String {
+    let mut ret = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(&next)
+                if c == '\\'
+                    && (matches!(next, '$' | '`' | '\\')
+                        || (next == '"' && quoting == Quoting::DoubleQuoted)) =>
+            {
+                ret.push(next);
+                chars.next();
+            }
+            _ => ret.push(c),
+        }
+    }
+    ret
+}
+
+/// Parses a `${...}` expansion, which is at `span` in the input.
+fn parse_braced(s: &str, span: Span) -> Result<Parameter, SyntaxError> {
+    let bad_substitution = || syntax_error(format!("{s}: bad substitution"), span);
+    let inner = &s[2..s.len() - 1];
+
+    if let Some(name) = inner
+        .strip_prefix('#')
+        .filter(|name| !name.is_empty() && parameter_name_len(name, true) == name.len())
+    {
+        return Ok(Parameter {
+            name: name.to_string(),
+            op: Some(ParameterOp::Length),
+            span,
+        });
+    }
+
+    let name_len = parameter_name_len(inner, true);
+    if name_len == 0 {
+        return Err(bad_substitution());
+    }
+    let (name, rest) = inner.split_at(name_len);
+
+    // Every word is a slice of `s`, so its offset is the distance between their starts.
+    let word = |w: &str| {
+        let start = w.as_ptr() as usize - s.as_ptr() as usize;
+        let span = span.slice(s, start..start + w.len());
+        parse_parts(w, Quoting::Unquoted, span).map(|parts| Word {
+            parts: tilde_prefixes(parts, false),
+            span,
+        })
+    };
+    let (colon, after_colon) = rest
+        .strip_prefix(':')
+        .map_or((false, rest), |rest| (true, rest));
+
+    let op = match after_colon.chars().next() {
+        None if !colon => None,
+        None => return Err(bad_substitution()),
+        Some('-') => Some(ParameterOp::Default {
+            colon,
+            word: word(&after_colon[1..])?,
+        }),
+        Some('=') => Some(ParameterOp::Assign {
+            colon,
+            word: word(&after_colon[1..])?,
+        }),
+        Some('?') => Some(ParameterOp::Error {
+            colon,
+            word: word(&after_colon[1..])?,
+        }),
+        Some('+') => Some(ParameterOp::Alternative {
+            colon,
+            word: word(&after_colon[1..])?,
+        }),
+        _ if colon => {
+            let (offset, length) = split_unquoted(after_colon, ':');
+            Some(ParameterOp::Substring {
+                offset: word(offset)?,
+                length: length.map(word).transpose()?,
+            })
+        }
+        Some('#') => {
+            let longest = rest.starts_with("##");
+            Some(ParameterOp::RemovePrefix {
+                longest,
+                pattern: word(&rest[if longest { 2 } else { 1 }..])?,
+            })
+        }
+        Some('%') => {
+            let longest = rest.starts_with("%%");
+            Some(ParameterOp::RemoveSuffix {
+                longest,
+                pattern: word(&rest[if longest { 2 } else { 1 }..])?,
+            })
+        }
+        Some('/') => {
+            let (mode, rest) = match rest[1..].chars().next() {
+                Some('/') => (ReplaceMode::All, &rest[2..]),
+                Some('#') => (ReplaceMode::Prefix, &rest[2..]),
+                Some('%') => (ReplaceMode::Suffix, &rest[2..]),
+                _ => (ReplaceMode::First, &rest[1..]),
+            };
+            let (pattern, replacement) = split_unquoted(rest, '/');
+            Some(ParameterOp::Replace {
+                mode,
+                pattern: word(pattern)?,
+                // An empty word at the end of the expansion, where a replacement would be.
+                replacement: word(replacement.unwrap_or_else(|| &rest[rest.len()..]))?,
+            })
+        }
+        Some(c @ ('^' | ',')) => {
+            let all = rest[1..].starts_with(c);
+            Some(ParameterOp::Case {
+                upper: c == '^',
+                all,
+                pattern: word(&rest[if all { 2 } else { 1 }..])?,
+            })
+        }
+        _ => return Err(bad_substitution()),
+    };
+
+    Ok(Parameter {
+        name: name.to_string(),
+        op,
+        span,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::lex;
+
+    fn parse_str(input: &str) -> Result<Script, SyntaxError> {
+        let tokens = lex(input, "test", Options::default()).map_err(|err| match err {
+            Error::Lex(err) => err,
+            err => panic!("{err}"),
+        })?;
+        Parser::new(tokens).script()
+    }
+
+    /// The first simple command of the script.
+    fn command(input: &str) -> SimpleCommand {
+        let script = parse_str(input).unwrap();
+        let Command::Simple(command) = &script.lists[0].and_ors[0].first.commands[0] else {
+            panic!("not a simple command: {input}");
+        };
+        command.clone()
+    }
+
+    fn words(input: &str) -> Vec<Word> {
+        command(input).words
+    }
+
+    fn text(input: &str, span: Span) -> &str {
+        &input[span.start..span.end]
+    }
+
+    /// The text of a word made of literal and quoted parts, with `$name` for parameters.
+    fn literal(word: &Word) -> String {
+        word.parts
+            .iter()
+            .map(|part| match &part.kind {
+                WordPartKind::Literal(text) | WordPartKind::SingleQuoted(text) => text.clone(),
+                WordPartKind::DoubleQuoted(parts) => literal(&Word {
+                    parts: parts.clone(),
+                    span: part.span,
+                }),
+                WordPartKind::Parameter(parameter) => format!("${}", parameter.name),
+                kind => panic!("not literal: {kind:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lists_and_pipelines() {
+        let input = "a | b && c || d; e &\nf";
+        let script = parse_str(input).unwrap();
+        assert_eq!(script.lists.len(), 2);
+        assert_eq!(text(input, script.span), input);
+
+        let list = &script.lists[0];
+        assert_eq!(text(input, list.span), "a | b && c || d; e &");
+        let [first, second] = &list.and_ors[..] else {
+            panic!("not two and-or chains: {list:?}");
+        };
+        assert_eq!(first.first.commands.len(), 2);
+        assert_eq!(text(input, first.first.span), "a | b");
+        assert_eq!(
+            first
+                .rest
+                .iter()
+                .map(|(connector, _)| *connector)
+                .collect::<Vec<Connector>>(),
+            [Connector::AndAnd, Connector::OrOr]
         );
+        assert!(!first.asynchronous);
+        assert!(second.asynchronous);
+        assert_eq!(text(input, second.span), "e");
     }
 
-    (0, parsed_tokens)
+    #[test]
+    fn linebreaks_after_operators() {
+        let script = parse_str("a &&\n\n b |\n c").unwrap();
+        assert_eq!(script.lists.len(), 1);
+        assert_eq!(script.lists[0].and_ors[0].rest[0].1.commands.len(), 2);
+    }
+
+    #[test]
+    fn assignments_and_words() {
+        let input = "x=1 y=~/a:~/b echo z=2 'a b'c";
+        let command = command(input);
+        let names = command
+            .assignments
+            .iter()
+            .map(|assignment| assignment.name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(names, ["x", "y"]);
+        assert_eq!(text(input, command.assignments[0].value.span), "1");
+        let kinds = command.assignments[1]
+            .value
+            .parts
+            .iter()
+            .map(|part| &part.kind)
+            .collect::<Vec<&WordPartKind>>();
+        assert_eq!(
+            kinds,
+            [
+                &WordPartKind::Tilde(String::new()),
+                &WordPartKind::Literal("/a:".to_string()),
+                &WordPartKind::Tilde(String::new()),
+                &WordPartKind::Literal("/b".to_string()),
+            ]
+        );
+        assert_eq!(
+            command.words.iter().map(literal).collect::<Vec<String>>(),
+            ["echo", "z=2", "a bc"]
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        for (input, message, column) in [
+            ("a |", "unexpected end of file", 4),
+            ("a;;", "unexpected ';'", 3),
+            ("&& a", "unexpected '&&'", 1),
+            (
+                "a >",
+                "expected a word after redirection, found end of file",
+                4,
+            ),
+            ("a > |", "expected a word after redirection, found '|'", 5),
+            ("echo ${a-b", "unterminated parameter expansion", 6),
+            ("echo ${}", "${}: bad substitution", 6),
+        ] {
+            let err = parse_str(input).unwrap_err();
+            assert_eq!(err.message, message, "{input}");
+            assert_eq!(err.span.column, column, "{input}");
+        }
+    }
+
+    #[test]
+    fn io_numbers_come_right_before_the_operator() {
+        let command = self::command("echo 2>&1");
+        assert_eq!(command.words.len(), 1);
+        let redirection = &command.redirections[0];
+        assert_eq!(redirection.kind, RedirectionKind::DupOutput);
+        assert_eq!(redirection.fd, Some(2));
+        assert_eq!(literal(&redirection.target), "1");
+
+        let command = self::command("echo 2 >&1");
+        assert_eq!(
+            command.words.iter().map(literal).collect::<Vec<String>>(),
+            ["echo", "2"]
+        );
+        assert_eq!(command.redirections[0].fd, None);
+
+        for input in ["echo a2>f", "echo '2'>f", "echo 2&>f"] {
+            let command = self::command(input);
+            assert_eq!(command.words.len(), 2, "{input}");
+            assert_eq!(command.redirections[0].fd, None, "{input}");
+        }
+    }
+
+    #[test]
+    fn fd_redirections() {
+        let input = "exec 3<>f 4<&0 5>&- >|g >>h <i &>j &>>k 10<<<l";
+        let command = command(input);
+        let redirections = command
+            .redirections
+            .iter()
+            .map(|redirection| {
+                (
+                    redirection.kind,
+                    redirection.fd,
+                    literal(&redirection.target),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            redirections,
+            [
+                (RedirectionKind::ReadWrite, Some(3), "f".to_string()),
+                (RedirectionKind::DupInput, Some(4), "0".to_string()),
+                (RedirectionKind::DupOutput, Some(5), "-".to_string()),
+                (RedirectionKind::Clobber, None, "g".to_string()),
+                (RedirectionKind::Append, None, "h".to_string()),
+                (RedirectionKind::Input, None, "i".to_string()),
+                (RedirectionKind::All, None, "j".to_string()),
+                (RedirectionKind::AllAppend, None, "k".to_string()),
+                (RedirectionKind::HereString, Some(10), "l".to_string()),
+            ]
+        );
+        assert_eq!(text(input, command.redirections[0].span), "3<>f");
+    }
+
+    #[test]
+    fn here_documents() {
+        let input = "cat <<A 3<<-'B'\nx $y\n\t$z\nA\n\tw $v\n\tB\n";
+        let command = command(input);
+        let [unquoted, quoted] = &command.redirections[..] else {
+            panic!("not two redirections: {command:?}");
+        };
+        assert_eq!(unquoted.kind, RedirectionKind::HereDoc);
+        assert_eq!(unquoted.fd, None);
+        let WordPartKind::DoubleQuoted(parts) = &unquoted.target.parts[0].kind else {
+            panic!("not expanded: {unquoted:?}");
+        };
+        let kinds = parts.iter().map(|part| &part.kind).collect::<Vec<_>>();
+        assert_eq!(kinds[0], &WordPartKind::Literal("x ".to_string()));
+        assert!(matches!(kinds[1], WordPartKind::Parameter(Parameter { name, .. }) if name == "y"));
+        assert_eq!(text(input, parts[1].span), "$y");
+        assert_eq!((parts[1].span.line, parts[1].span.column), (2, 3));
+
+        assert_eq!(quoted.fd, Some(3));
+        assert_eq!(
+            quoted.target.parts[0].kind,
+            WordPartKind::SingleQuoted("w $v\n".to_string())
+        );
+    }
+
+    #[test]
+    fn parts_point_into_the_word() {
+        let input = "echo a'b'$c${d}\"e\"";
+        let word = &words(input)[1];
+        let parts = word
+            .parts
+            .iter()
+            .map(|part| text(input, part.span))
+            .collect::<Vec<&str>>();
+        assert_eq!(parts, ["a", "'b'", "$c", "${d}", "\"e\""]);
+        assert_eq!(word.parts[4].span.column, 16);
+    }
+
+    #[test]
+    fn spans_leave_out_trailing_spaces() {
+        let input = "a b  | c ;  d  ";
+        let list = &parse_str(input).unwrap().lists[0];
+        assert_eq!(text(input, list.span), "a b  | c ;  d");
+        let pipeline = &list.and_ors[0].first;
+        assert_eq!(text(input, pipeline.span), "a b  | c");
+        let Command::Simple(command) = &pipeline.commands[0] else {
+            panic!("not a simple command: {pipeline:?}");
+        };
+        assert_eq!(text(input, command.span), "a b");
+    }
+
+    #[test]
+    fn nested_words_and_scripts_point_into_the_input() {
+        let input = "echo ${x:-$(true)}";
+        let word = &words(input)[1];
+        let WordPartKind::Parameter(Parameter {
+            op: Some(ParameterOp::Default { word, .. }),
+            ..
+        }) = &word.parts[0].kind
+        else {
+            panic!("not a default: {word:?}");
+        };
+        assert_eq!(text(input, word.span), "$(true)");
+        let WordPartKind::CommandSubstitution(script) = &word.parts[0].kind else {
+            panic!("not a command substitution: {word:?}");
+        };
+        assert_eq!(text(input, script.span), "true");
+        assert_eq!(script.span.column, 13);
+
+        let input = "echo \"$(\n  true)\"";
+        let WordPartKind::DoubleQuoted(parts) = &words(input)[1].parts[0].kind else {
+            panic!("not double-quoted");
+        };
+        let WordPartKind::CommandSubstitution(script) = &parts[0].kind else {
+            panic!("not a command substitution");
+        };
+        assert_eq!(text(input, script.span), "true");
+        assert_eq!((script.span.line, script.span.column), (2, 3));
+    }
+
+    #[test]
+    fn errors_in_substitutions_point_into_the_input() {
+        let err = parse_str("echo $(echo |)").unwrap_err();
+        assert_eq!(err.message, "unexpected end of file");
+        assert_eq!((err.span.line, err.span.column), (1, 14));
+
+        let err = parse_str("echo `\necho ;;`").unwrap_err();
+        assert_eq!(err.message, "unexpected ';'");
+        assert_eq!((err.span.line, err.span.column), (2, 7));
+    }
 }
@@ -0,0 +1,167 @@
+/// Returns whether the whole of `text` matches the shell pattern.
+///
+/// `*` matches any string, `?` any character and `[...]` any character of the bracket
+/// expression, which may be negated with `!` or `^` and contain ranges and `[:class:]`
+/// names. A backslash makes the next character match itself.
+#[must_use]
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let text = text.chars().collect::<Vec<char>>();
+    matches_from(&pattern, &text)
+}
+
+fn matches_from(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the last `*`: the pattern index after it and the text index
+    // it is currently assumed to stop at.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match_bracket(&pattern[p..], text[t]),
+            Some('\\') if p + 1 < pattern.len() => (pattern[p + 1] == text[t]).then_some(2),
+            Some(&c) => (c == text[t]).then_some(1),
+            None => None,
+        };
+
+        match step {
+            Some(len) => {
+                p += len;
+                t += 1;
+            }
+            None => match backtrack {
+                Some((star_p, star_t)) => {
+                    backtrack = Some((star_p, star_t + 1));
+                    p = star_p;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Matches `c` against the bracket expression at the start of `pattern`, returning the
+/// length of the expression if it matches.
+///
+/// An unterminated `[` is an ordinary character.
+fn match_bracket(pattern: &[char], c: char) -> Option<usize> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match pattern.get(i) {
+            None => return (c == '[').then_some(1),
+            Some(']') if !first => break,
+            Some('[') if pattern.get(i + 1) == Some(&':') => {
+                let name_start = i + 2;
+                let Some(len) = pattern[name_start..]
+                    .windows(2)
+                    .position(|window| window == [':', ']'])
+                else {
+                    return (c == '[').then_some(1);
+                };
+                let name = pattern[name_start..name_start + len]
+                    .iter()
+                    .collect::<String>();
+                matched |= class_matches(&name, c);
+                i = name_start + len + 2;
+            }
+            Some(&start) => {
+                let (start, mut next) = if start == '\\' && i + 1 < pattern.len() {
+                    (pattern[i + 1], i + 2)
+                } else {
+                    (start, i + 1)
+                };
+                if pattern.get(next) == Some(&'-')
+                    && pattern.get(next + 1).is_some_and(|&end| end != ']')
+                {
+                    let end = pattern[next + 1];
+                    matched |= (start..=end).contains(&c);
+                    next += 2;
+                } else {
+                    matched |= start == c;
+                }
+                i = next;
+            }
+        }
+        first = false;
+    }
+
+    (matched != negated).then_some(i + 1)
+}
+
+fn class_matches(name: &str, c: char) -> bool {
+    match name {
+        "alnum" => c.is_alphanumeric(),
+        "alpha" => c.is_alphabetic(),
+        "blank" => c == ' ' || c == '\t',
+        "cntrl" => c.is_control(),
+        "digit" => c.is_ascii_digit(),
+        "graph" => !c.is_whitespace() && !c.is_control(),
+        "lower" => c.is_lowercase(),
+        "print" => !c.is_control(),
+        "punct" => c.is_ascii_punctuation(),
+        "space" => c.is_whitespace(),
+        "upper" => c.is_uppercase(),
+        "xdigit" => c.is_ascii_hexdigit(),
+        _ => false,
+    }
+}
+
+/// Returns whether the pattern holds an unescaped `*`, `?` or `[`, so it can match more
+/// than itself.
+#[must_use]
+pub fn has_wildcards(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' | '?' | '[' => return true,
+            '\\' => {
+                chars.next();
+            }
+            _ => (),
+        }
+    }
+    false
+}
+
+/// Removes the backslashes escaping characters in a pattern without wildcards.
+#[must_use]
+pub fn unescape(pattern: &str) -> String {
+    let mut ret = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => ret.extend(chars.next()),
+            _ => ret.push(c),
+        }
+    }
+    ret
+}
+
+/// Escapes the characters that are special in patterns, so `text` only matches itself.
+#[must_use]
+pub fn escape(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            ret.push('\\');
+        }
+        ret.push(c);
+    }
+    ret
+}
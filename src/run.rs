@@ -1,66 +1,815 @@
-use std::io::Read;
-use std::path::Path;
+use crate::builtins::{self, Streams};
+use crate::error::Error;
+use crate::parse::{
+    AndOr, Command, Connector, List, Pipeline, Redirection, RedirectionKind, Script, SimpleCommand,
+};
+use crate::vars::{Variable, Variables};
 use crate::Options;
-use crate::parse::{ParsedToken, ParsedTokens};
-
-/// Executes the parsed tokens.
-pub fn run(tokens: ParsedTokens, options: Options) -> i32 {
-    for tok in tokens {
-        match tok.clone() {
-            ParsedToken::Command(name, args) => {
-                run_command(&tok);
+use log::info;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::process::{Child, ExitStatus};
+use std::sync::Arc;
+
+/// Executes the parsed script, returning the status of the last list that ran.
+///
+/// # Errors
+///
+/// Errors that only fail a single command are reported and become its status. Fatal ones,
+/// such as `${name:?}` of an unset parameter, stop the script and are returned.
+pub fn run(script: &Script, interpreter: &mut Interpreter) -> Result<i32, Error> {
+    if interpreter.options.verbose {
+        info!("Running script...");
+    }
+
+    let mut status = 0;
+    for list in &script.lists {
+        status = interpreter.run_list(list)?;
+    }
+    Ok(status)
+}
+
+/// The state of a running shell, kept from one script to the next.
+#[derive(Clone, Debug)]
+pub struct Interpreter {
+    pub options: Options,
+    pub(crate) vars: Variables,
+    /// `$0`, the name of the shell or script.
+    pub(crate) arg0: String,
+    /// The positional parameters `$1`, `$2` and so on.
+    pub(crate) args: Vec<String>,
+    /// `$$`, the process ID of the shell, which subshells keep.
+    pub(crate) pid: u32,
+    /// `$?`, the status of the last pipeline.
+    pub(crate) status: i32,
+    /// The status of the last command substitution of the command being expanded, which
+    /// is the status of a command made only of assignments.
+    pub(crate) substitution_status: Option<i32>,
+    /// `$!`, the process ID of the last asynchronous pipeline.
+    pub(crate) last_background: Option<libc::pid_t>,
+    /// The subshells of asynchronous chains that have not been waited on yet.
+    pub(crate) background: Vec<libc::pid_t>,
+    /// The directory stack of `pushd` and `popd`, below the current directory.
+    pub(crate) dir_stack: Vec<String>,
+    /// The status the `exit` builtin asked the shell to exit with.
+    pub(crate) exiting: Option<i32>,
+    /// Where commands write their output when it is not redirected. `None` is the shell's
+    /// own stdout.
+    pub(crate) stdout: Option<Arc<OwnedFd>>,
+}
+
+impl Interpreter {
+    /// Creates an interpreter whose variables are taken from the process environment.
+    ///
+    /// `$PWD` is kept only if it is a path to the current directory.
+    #[must_use]
+    pub fn new(options: Options) -> Self {
+        let mut interpreter = Self {
+            options,
+            vars: Variables::from_env(),
+            arg0: "qiish".to_string(),
+            args: vec![],
+            pid: std::process::id(),
+            status: 0,
+            substitution_status: None,
+            last_background: None,
+            background: vec![],
+            dir_stack: vec![],
+            exiting: None,
+            stdout: None,
+        };
+        let pwd = builtins::current_pwd(&interpreter);
+        // Nothing is readonly yet.
+        let _ = interpreter.vars.set("PWD", pwd);
+        interpreter
+    }
+
+    /// Sets `$0` to `name` and the positional parameters to `args`.
+    pub fn set_args(&mut self, name: String, args: Vec<String>) {
+        self.arg0 = name;
+        self.args = args;
+    }
+
+    /// The status of the last pipeline, `$?`.
+    #[must_use]
+    pub const fn status(&self) -> i32 {
+        self.status
+    }
+
+    /// Sets `$?`, as after a command line that could not be run.
+    pub const fn set_status(&mut self, status: i32) {
+        self.status = status;
+    }
+
+    /// The shell variables.
+    #[must_use]
+    pub const fn vars(&self) -> &Variables {
+        &self.vars
+    }
+
+    /// Executes every and-or chain of a [`List`] in turn.
+    ///
+    /// Asynchronous chains are started without waiting for them, with a status of zero.
+    fn run_list(&mut self, list: &List) -> Result<i32, Error> {
+        // Asynchronous chains that have finished are waited on, so they do not linger as
+        // zombies.
+        self.background.retain(|&pid| {
+            // SAFETY: a null status is not written to.
+            unsafe { libc::waitpid(pid, std::ptr::null_mut(), libc::WNOHANG) == 0 }
+        });
+
+        for and_or in &list.and_ors {
+            if and_or.asynchronous {
+                self.status = self
+                    .run_asynchronously(and_or)
+                    .map_or_else(|err| err.report(), |()| 0);
+            } else {
+                self.run_and_or(and_or)?;
             }
-            _ => {}
         }
+        Ok(self.status)
     }
-    0
-}
 
-/// Executes a [`ParsedToken::Command`].
-fn run_command(command: &ParsedToken) -> i32 {
-    match command {
-        ParsedToken::Command(name, args) => {
-            let mut args = args.clone();
-            let command_real = find_command(name);
+    /// Starts an and-or chain in the background, in a subshell of its own, whose process
+    /// ID becomes `$!`.
+    ///
+    /// As there is no job control, the stdin of the chain is `/dev/null` rather than the
+    /// shell's.
+    fn run_asynchronously(&mut self, and_or: &AndOr) -> Result<(), Error> {
+        let pid = self.fork_subshell(|subshell| {
+            if let Err(source) = null_stdin() {
+                return Error::Io {
+                    context: "cannot open /dev/null",
+                    source,
+                }
+                .report();
+            }
+            subshell
+                .run_and_or(and_or)
+                .unwrap_or_else(|err| err.report())
+        })?;
+        self.last_background = Some(pid);
+        self.background.push(pid);
+        Ok(())
+    }
 
-            if command_real.is_none() {
-                return 127;
+    /// Executes the pipelines of an [`AndOr`] from left to right.
+    ///
+    /// A pipeline after `&&` only runs if the previous status is zero, and one after `||`
+    /// only if it is non-zero. Skipped pipelines leave the status untouched, so in
+    /// `false && a || b` `b` still runs.
+    fn run_and_or(&mut self, and_or: &AndOr) -> Result<i32, Error> {
+        self.status = wait(self.start_pipeline(&and_or.first)?);
+        for (connector, pipeline) in &and_or.rest {
+            let should_run = match connector {
+                Connector::AndAnd => self.status == 0,
+                Connector::OrOr => self.status != 0,
+            };
+            if should_run {
+                self.status = wait(self.start_pipeline(pipeline)?);
             }
+        }
+        Ok(self.status)
+    }
 
-            let command_real = command_real.unwrap();
-            let command_real = Path::new(&command_real);
+    /// Starts the commands of a [`Pipeline`], returning its stages.
+    ///
+    /// Every stage is started before any of them is waited on, with the stdout of each
+    /// stage connected to the stdin of the next one. A fatal error in any stage is
+    /// returned once the stages started before it have finished.
+    fn start_pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<Stage>, Error> {
+        let mut stages = Vec::with_capacity(pipeline.commands.len());
+        let mut stdin: Option<OwnedFd> = None;
 
-            execute_command(command_real, args)
+        for (i, command) in pipeline.commands.iter().enumerate() {
+            let mut io = Io::default();
+            if let Some(stdin) = stdin.take() {
+                io.set(0, Arc::new(stdin));
+            }
+
+            if i + 1 < pipeline.commands.len() {
+                match std::io::pipe() {
+                    Ok((reader, writer)) => {
+                        io.set(1, Arc::new(writer.into()));
+                        stdin = Some(reader.into());
+                    }
+                    Err(source) => {
+                        let err = Error::Io {
+                            context: "cannot create pipe",
+                            source,
+                        };
+                        stages.push(Stage::Done(err.report()));
+                        break;
+                    }
+                }
+            }
+
+            // `io` is dropped once the stage has been started, so that only the children
+            // keep the pipe ends open and readers see end of file when their writer exits.
+            match self.run_command(command, io, pipeline.commands.len() > 1) {
+                Ok(stage) => stages.push(stage),
+                Err(err) => {
+                    wait(stages);
+                    return Err(err);
+                }
+            }
         }
-        _ => 1,
+
+        Ok(stages)
     }
+
+    /// Starts a [`Command`], which is one stage of a pipeline of several if `in_pipeline`
+    /// is set.
+    ///
+    /// Such a stage runs in a subshell of its own, so that it runs alongside the others
+    /// however much a builtin writes, and nothing it does, as in `x=1 | cat` or
+    /// `cd / | cat`, changes the shell itself.
+    fn run_command(
+        &mut self,
+        command: &Command,
+        mut io: Io,
+        in_pipeline: bool,
+    ) -> Result<Stage, Error> {
+        if let Some(stdout) = &self.stdout {
+            io.fds.entry(1).or_insert_with(|| Some(Arc::clone(stdout)));
+        }
+        if !in_pipeline {
+            return self.start_command(command, io, false);
+        }
+
+        self.fork_subshell(|subshell| {
+            // Its file descriptor has been installed as stdout, if it was not replaced, and
+            // closed.
+            std::mem::forget(subshell.stdout.take());
+            let io = io.install().map_err(|source| Error::Io {
+                context: "cannot duplicate file descriptor",
+                source,
+            });
+            io.and_then(|io| subshell.start_command(command, io, true))
+                .map_or_else(|err| err.report(), Stage::wait)
+        })
+        .map(Stage::Subshell)
+    }
+
+    /// Starts a [`Command`] in this shell. If it is all that is left for a subshell to
+    /// run, `exec` is set, and an external command replaces the subshell.
+    ///
+    /// An error that is not fatal is reported, and the command finishes with its status.
+    fn start_command(&mut self, command: &Command, io: Io, exec: bool) -> Result<Stage, Error> {
+        let stage = match command {
+            Command::Simple(command) => self.run_simple_command(command, io, exec),
+            Command::Arithmetic(word) => self
+                .evaluate_arithmetic(&word.parts)
+                .map(|value| Stage::Done(i32::from(value == 0))),
+        };
+        match stage {
+            Err(err) if !err.is_fatal() => Ok(Stage::Done(err.report())),
+            stage => stage,
+        }
+    }
+
+    /// Starts a [`SimpleCommand`].
+    ///
+    /// Without a command name, the assignments set shell variables, and the status is that
+    /// of the last command substitution, if any. Otherwise they are only added to the
+    /// environment of the command. Builtins run in the shell itself, and other commands
+    /// are spawned, or replace the shell with `exec`.
+    fn run_simple_command(
+        &mut self,
+        command: &SimpleCommand,
+        mut io: Io,
+        exec: bool,
+    ) -> Result<Stage, Error> {
+        self.substitution_status = None;
+        // The words are expanded first, so that `echo "$(cat f)" > f` reads `f` before the
+        // redirection truncates it.
+        let (assignments, words) = self.expand_command(command)?;
+        self.apply_redirections(&command.redirections, &mut io)?;
+
+        let mut words = words.into_iter();
+        let Some(name) = words.next() else {
+            for (name, value) in assignments {
+                self.vars.set(&name, value)?;
+            }
+            return Ok(Stage::Done(self.substitution_status.unwrap_or(0)));
+        };
+        let args = words.collect::<Vec<String>>();
+        if let Some((name, _)) = assignments
+            .iter()
+            .find(|(name, _)| self.vars.is_readonly(name))
+        {
+            return Err(Error::Readonly(name.clone()));
+        }
+
+        if let Some(builtin) = builtins::find(&name) {
+            return self.run_builtin(builtin, &args, assignments, &io);
+        }
+
+        let Some(command_real) = self.find_command(&name) else {
+            return Err(Error::CommandNotFound(name));
+        };
+
+        // The assignments come last, so they override the exported variables.
+        let env = self
+            .vars
+            .environment()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .chain(assignments)
+            .collect::<Vec<(String, String)>>();
+        // Here-documents are fed from a thread of this process, which must outlive the
+        // command.
+        let exec = exec
+            && !command.redirections.iter().any(|redirection| {
+                matches!(
+                    redirection.kind,
+                    RedirectionKind::HereDoc | RedirectionKind::HereString
+                )
+            });
+        execute_command(&name, Path::new(&command_real), args, &env, &io, exec)
+    }
+
+    /// Runs a builtin in the shell itself.
+    ///
+    /// The assignments are exported variables for as long as the builtin runs, as in
+    /// `HOME=/tmp cd`. Those the builtin leaves alone are then put back as they were, while
+    /// `PWD=/foo cd /usr` keeps the `$PWD` set by `cd`.
+    fn run_builtin(
+        &mut self,
+        builtin: builtins::Builtin,
+        args: &[String],
+        assignments: Vec<(String, String)>,
+        io: &Io,
+    ) -> Result<Stage, Error> {
+        let mut streams = io.streams().map_err(|source| Error::Io {
+            context: "cannot duplicate file descriptor",
+            source,
+        })?;
+
+        let saved = assignments
+            .into_iter()
+            .map(|(name, value)| {
+                let variable = Variable {
+                    value: Some(value.clone()),
+                    exported: true,
+                    readonly: false,
+                };
+                let old = self.vars.replace(&name, Some(variable));
+                (name, value, old)
+            })
+            .collect::<Vec<_>>();
+        let status = builtin(self, args, &mut streams);
+        // In reverse, so that the first of `x=1 x=2` puts back the original.
+        for (name, value, old) in saved.into_iter().rev() {
+            if self.vars.get(&name) == Some(value.as_str()) && !self.vars.is_readonly(&name) {
+                self.vars.replace(&name, old);
+            }
+        }
+        // Whatever could not be written has already been reported as a write error.
+        let _ = streams.stdout.flush();
+        if let Some(status) = self.exiting.take() {
+            return Err(Error::Exit(status));
+        }
+        Ok(Stage::Done(status))
+    }
+
+    /// Forks a subshell, a copy of the shell that runs `f` and exits with the status it
+    /// returns, and returns its process ID.
+    fn fork_subshell(&mut self, f: impl FnOnce(&mut Self) -> i32) -> Result<libc::pid_t, Error> {
+        // Whatever is still buffered would otherwise be written by both shells.
+        let _ = std::io::stdout().flush();
+        // SAFETY: the subshell never returns from here. The other threads of the shell only
+        // feed here-documents and read command substitutions, so they hold no lock the
+        // subshell could wait on.
+        match unsafe { libc::fork() } {
+            -1 => Err(Error::Io {
+                context: "cannot fork",
+                source: std::io::Error::last_os_error(),
+            }),
+            0 => {
+                // Like the commands the shell spawns, the subshell is killed by `SIGPIPE`
+                // once its reader has exited.
+                unsafe {
+                    libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+                }
+                // A panic must not unwind into the rest of the script.
+                let status = std::panic::catch_unwind(AssertUnwindSafe(|| f(self))).unwrap_or(101);
+                let _ = std::io::stdout().flush();
+                // SAFETY: nothing is left to clean up, and the parent's `atexit` handlers are
+                // not the subshell's to run.
+                unsafe { libc::_exit(status) }
+            }
+            pid => Ok(pid),
+        }
+    }
+
+    /// Expands the assignments and words of a command.
+    #[allow(clippy::type_complexity)]
+    fn expand_command(
+        &mut self,
+        command: &SimpleCommand,
+    ) -> Result<(Vec<(String, String)>, Vec<String>), Error> {
+        let mut assignments = Vec::with_capacity(command.assignments.len());
+        for assignment in &command.assignments {
+            let value = self.expand_to_string(&assignment.value)?;
+            assignments.push((assignment.name.clone(), value));
+        }
+
+        let mut words = vec![];
+        for word in &command.words {
+            words.extend(self.expand_word(word)?);
+        }
+
+        Ok((assignments, words))
+    }
+
+    /// Opens the files named by the redirections, in order, and installs them into `io`.
+    ///
+    /// Redirections are applied after the pipeline has been wired up, so they take
+    /// precedence over the pipe ends, and each one sees the file descriptors as the ones
+    /// before it left them: `> out 2>&1` sends both stdout and stderr to `out`, while
+    /// `2>&1 > out` only sends stdout there.
+    fn apply_redirections(
+        &mut self,
+        redirections: &[Redirection],
+        io: &mut Io,
+    ) -> Result<(), Error> {
+        for redirection in redirections {
+            let target = self.expand_to_string(&redirection.target)?;
+            let fd = redirection.fd.unwrap_or(match redirection.kind {
+                RedirectionKind::Input
+                | RedirectionKind::HereDoc
+                | RedirectionKind::HereString
+                | RedirectionKind::ReadWrite
+                | RedirectionKind::DupInput => 0,
+                _ => 1,
+            });
+
+            let file = match redirection.kind {
+                // `>` and `>|` only differ once `noclobber` is supported.
+                RedirectionKind::Output | RedirectionKind::Clobber | RedirectionKind::All => {
+                    File::create(&target).map(OwnedFd::from)
+                }
+                RedirectionKind::Append | RedirectionKind::AllAppend => OpenOptions::new()
+                    .append(true)
+                    .create(true)
+                    .open(&target)
+                    .map(OwnedFd::from),
+                RedirectionKind::Input => File::open(&target).map(OwnedFd::from),
+                RedirectionKind::ReadWrite => OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(&target)
+                    .map(OwnedFd::from),
+                RedirectionKind::HereDoc => feed(&target),
+                RedirectionKind::HereString => feed(&format!("{target}\n")),
+                RedirectionKind::DupInput | RedirectionKind::DupOutput => {
+                    let result = if target == "-" {
+                        io.close(fd);
+                        Ok(())
+                    } else {
+                        target.parse().map_or_else(
+                            |_| Err(std::io::Error::from_raw_os_error(libc::EBADF)),
+                            |source| io.duplicate(fd, source),
+                        )
+                    };
+                    result.map_err(|source| Error::Redirection { target, source })?;
+                    continue;
+                }
+            };
+
+            let file = Arc::new(file.map_err(|source| match redirection.kind {
+                RedirectionKind::HereDoc | RedirectionKind::HereString => Error::Io {
+                    context: "cannot create here-document",
+                    source,
+                },
+                _ => Error::Redirection { target, source },
+            })?);
+            if matches!(
+                redirection.kind,
+                RedirectionKind::All | RedirectionKind::AllAppend
+            ) {
+                io.set(1, Arc::clone(&file));
+                io.set(2, file);
+            } else {
+                io.set(fd, file);
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds the command in the directories of `$PATH`, or returns it as it is if it holds
+    /// a `/`.
+    ///
+    /// An empty directory is the current one. A file that is not executable is only
+    /// returned if no executable one is found, so that running it fails with the right
+    /// error.
+    fn find_command(&self, command: &str) -> Option<String> {
+        if command.contains('/') {
+            return Some(command.to_string());
+        }
+
+        let path = self.vars.get("PATH").unwrap_or_default();
+        let mut found = None;
+        for dir in path.split(':') {
+            let dir = if dir.is_empty() { "." } else { dir };
+            let path = format!("{dir}/{command}");
+            let Ok(metadata) = std::fs::metadata(&path) else {
+                continue;
+            };
+            if metadata.is_dir() {
+                continue;
+            }
+            if metadata.permissions().mode() & 0o111 != 0 {
+                info!("Found command: {}", path);
+                return Some(path);
+            }
+            found.get_or_insert(path);
+        }
+        found
+    }
+}
+
+/// The file descriptors handed to a command, by the number the command sees them as.
+///
+/// File descriptors missing from the table are inherited from the shell, and `None` closes
+/// one.
+#[derive(Debug, Default)]
+struct Io {
+    fds: BTreeMap<RawFd, Option<Arc<OwnedFd>>>,
+}
+
+impl Io {
+    /// Makes `fd` refer to `file`.
+    fn set(&mut self, fd: RawFd, file: Arc<OwnedFd>) {
+        self.fds.insert(fd, Some(file));
+    }
+
+    /// Closes `fd`.
+    fn close(&mut self, fd: RawFd) {
+        self.fds.insert(fd, None);
+    }
+
+    /// The file descriptors to install in a child process, sorted, each with the one it is
+    /// taken from, or `None` to close it.
+    fn moves(&self) -> Vec<(RawFd, Option<RawFd>)> {
+        self.fds
+            .iter()
+            .map(|(&fd, file)| (fd, file.as_ref().map(AsRawFd::as_raw_fd)))
+            .collect()
+    }
+
+    /// Installs the file descriptors in a forked subshell, and closes every other one it
+    /// inherited. Returns the file descriptors as the subshell now sees them, where only
+    /// the closed ones are not inherited.
+    fn install(&self) -> std::io::Result<Self> {
+        let mut moves = self.moves();
+        install_fds(&mut moves)?;
+        let installed = moves
+            .iter()
+            .filter(|(_, source)| source.is_some())
+            .map(|&(fd, _)| fd)
+            .collect::<Vec<RawFd>>();
+        close_other_fds(&installed);
+        Ok(Self {
+            fds: self
+                .fds
+                .iter()
+                .filter(|(_, file)| file.is_none())
+                .map(|(&fd, _)| (fd, None))
+                .collect(),
+        })
+    }
+
+    /// Returns the stdout and stderr of a builtin run with these file descriptors.
+    fn streams(&self) -> std::io::Result<Streams> {
+        Ok(Streams {
+            stdout: self.writer(1, || Box::new(std::io::stdout()))?,
+            stderr: self.writer(2, || Box::new(std::io::stderr()))?,
+        })
+    }
+
+    /// Returns a writer to `fd`, or the shell's own stream if it is inherited.
+    fn writer(
+        &self,
+        fd: RawFd,
+        inherited: impl FnOnce() -> Box<dyn Write>,
+    ) -> std::io::Result<Box<dyn Write>> {
+        Ok(match self.fds.get(&fd) {
+            Some(Some(file)) => Box::new(File::from(file.try_clone()?)),
+            Some(None) => Box::new(Closed),
+            None => inherited(),
+        })
+    }
+
+    /// Makes `fd` refer to what `source` refers to so far, as in `2>&1`.
+    fn duplicate(&mut self, fd: RawFd, source: RawFd) -> std::io::Result<()> {
+        let file = match self.fds.get(&source) {
+            Some(Some(file)) => Arc::clone(file),
+            Some(None) => return Err(std::io::Error::from_raw_os_error(libc::EBADF)),
+            None => Arc::new(duplicate(source)?),
+        };
+        self.set(fd, file);
+        Ok(())
+    }
+}
+
+/// A file descriptor closed with `>&-`, which fails every write.
+struct Closed;
+
+impl Write for Closed {
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::from_raw_os_error(libc::EBADF))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A command that has been started as part of a pipeline.
+#[derive(Debug)]
+enum Stage {
+    /// A child process that has not been waited on yet.
+    Running(Child),
+    /// A forked subshell that has not been waited on yet.
+    Subshell(libc::pid_t),
+    /// A command that has already finished, or could not be started, with its status.
+    Done(i32),
 }
 
-fn execute_command(command: &Path, args: Vec<String>) -> i32{
+impl Stage {
+    /// Waits for the stage to finish and returns its exit status, which is 128 plus the
+    /// signal number for a child killed by a signal.
+    fn wait(self) -> i32 {
+        match self {
+            Self::Running(mut child) => child.wait().map_or(1, exit_status),
+            Self::Subshell(pid) => wait_pid(pid).map_or(1, exit_status),
+            Self::Done(status) => status,
+        }
+    }
+}
+
+/// Returns the status a process exited with.
+fn exit_status(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
+
+/// Waits for the child process `pid` to finish.
+fn wait_pid(pid: libc::pid_t) -> std::io::Result<ExitStatus> {
+    let mut status = 0;
+    loop {
+        // SAFETY: `status` is a valid place for the status to be written to.
+        if unsafe { libc::waitpid(pid, &raw mut status, 0) } >= 0 {
+            return Ok(ExitStatus::from_raw(status));
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Waits for every stage of a pipeline, returning the status of the last one.
+fn wait(stages: Vec<Stage>) -> i32 {
+    stages.into_iter().fold(0, |_, stage| stage.wait())
+}
+
+/// Returns the read end of a pipe that yields `text`.
+///
+/// The text is written from a separate thread, so a command that reads slowly, or not at
+/// all, cannot block the shell on a full pipe.
+fn feed(text: &str) -> std::io::Result<OwnedFd> {
+    let (reader, mut writer) = std::io::pipe()?;
+    let text = text.to_owned();
+    std::thread::spawn(move || {
+        // The command is free to exit without reading everything.
+        let _ = writer.write_all(text.as_bytes());
+    });
+    Ok(reader.into())
+}
+
+/// Makes `/dev/null` the stdin of the process.
+fn null_stdin() -> std::io::Result<()> {
+    let null = File::open("/dev/null")?;
+    // SAFETY: stdin is not owned by anything that would close it.
+    if unsafe { libc::dup2(null.as_raw_fd(), 0) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
 
-    // TEMPORARY
-    // TODO: Make this work for real
-    let mut child = unsafe {
-        std::process::Command::new(command)
-            .args(args)
-            .spawn()
-            .expect("Failed to execute command")
+/// Duplicates one of the shell's own file descriptors, failing if it is not open.
+fn duplicate(fd: RawFd) -> std::io::Result<OwnedFd> {
+    // SAFETY: `fcntl` fails rather than touching a file descriptor that is not open.
+    let duplicate = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+    if duplicate < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: the duplicate is new, so nothing else owns it.
+    Ok(unsafe { OwnedFd::from_raw_fd(duplicate) })
+}
+
+/// Closes every file descriptor of the process but stdin, stdout, stderr and `keep`.
+///
+/// This is for a subshell that does not execute a program, where close-on-exec leaves
+/// open the pipe ends of other stages, which would keep them from seeing end of file. The
+/// owners of these file descriptors are never dropped, as the subshell exits without
+/// returning.
+fn close_other_fds(keep: &[RawFd]) {
+    let Ok(entries) = std::fs::read_dir("/dev/fd") else {
+        return;
     };
+    let fds = entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect::<Vec<RawFd>>();
+    for fd in fds {
+        if fd > 2 && !keep.contains(&fd) {
+            // SAFETY: closing a file descriptor that is not open, such as the one the
+            // directory was read from, is harmless.
+            unsafe {
+                libc::close(fd);
+            }
+        }
+    }
+}
 
-    let exit_code = child.wait().unwrap();
-    exit_code.code().unwrap_or(1)
+/// Installs the file descriptors of a command in the child process, right before it
+/// executes the command. `moves` holds the file descriptor each one should become, and the
+/// one it is taken from, or `None` to close it.
+///
+/// The sources are first moved above every file descriptor to install, so installing one
+/// cannot overwrite a source that is still to be installed.
+fn install_fds(moves: &mut [(RawFd, Option<RawFd>)]) -> std::io::Result<()> {
+    let above = moves.last().map_or(0, |&(fd, _)| fd + 1);
+    for (_, source) in moves.iter_mut() {
+        if let Some(source) = source {
+            // SAFETY: the child process owns every file descriptor it inherited.
+            let high = unsafe { libc::fcntl(*source, libc::F_DUPFD_CLOEXEC, above) };
+            if high < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            *source = high;
+        }
+    }
+    for &(fd, source) in moves.iter() {
+        // SAFETY: as above. Closing a file descriptor that is not open is harmless.
+        match source {
+            Some(source) if unsafe { libc::dup2(source, fd) } < 0 => {
+                return Err(std::io::Error::last_os_error());
+            }
+            Some(_) => (),
+            None => unsafe {
+                libc::close(fd);
+            },
+        }
+    }
+    Ok(())
 }
 
-/// Finds the command in the PATH.
-fn find_command(command: &String) -> Option<String> {
-    let path = std::env::var("PATH").unwrap();
-    let path = path.split(':').collect::<Vec<&str>>();
-    for dir in path {
-        let path = format!("{}/{}", dir, command);
-        if std::path::Path::new(&path).exists() {
-            println!("Found command: {}", path);
-            return Some(path);
+/// Spawns the program at `path` for the command called `name`, with `env` as its whole
+/// environment, or with `exec` replaces the process with it.
+fn execute_command(
+    name: &str,
+    path: &Path,
+    args: Vec<String>,
+    env: &[(String, String)],
+    io: &Io,
+    exec: bool,
+) -> Result<Stage, Error> {
+    let mut child = std::process::Command::new(path);
+    child.args(args);
+    child.env_clear();
+    child.envs(env.iter().map(|(name, value)| (name, value)));
+
+    // The table stays open until the child has been spawned.
+    let mut moves = io.moves();
+    if !moves.is_empty() {
+        // SAFETY: the closure runs between fork and exec, where it only makes system calls
+        // that are safe there, without allocating.
+        unsafe {
+            child.pre_exec(move || install_fds(&mut moves));
         }
     }
-    None
-}
\ No newline at end of file
+
+    let result = if exec {
+        // Only returns if the program could not be executed.
+        Err(child.exec())
+    } else {
+        child.spawn().map(Stage::Running)
+    };
+    result.map_err(|source| Error::Exec {
+        command: name.to_string(),
+        source,
+    })
+}
@@ -1,28 +1,15 @@
 #![allow(dead_code)]
 
+use qiish::error::Error;
+use qiish::run::Interpreter;
+use qiish::{lex, parse, run, tilde, Options};
 use std::env;
-use std::io::{stdin, stdout, Write};
-use std::path::PathBuf;
+use std::io::{stdout, Write};
 use std::vec::IntoIter;
 
-
-/// Lex's the input string into a vector of tokens.
-#[path = "lex.rs"]
-pub mod lex;
-/// Implements a Peekable-like trait so you can peek multiple items ahead.
-#[path = "lookahead.rs"]
-pub mod lookahead;
-/// Parses the vector of tokens into a vector of parsed tokens.
-#[path = "parse.rs"]
-pub mod parse;
-/// Runs the shell.
-#[path = "run.rs"]
-pub mod run;
-/// Options for the shell.
-#[path = "options.rs"]
-pub mod options;
-
-use options::Options;
+/// How many end of files in a row the `ignoreeof` option ignores before the shell exits
+/// anyway.
+const IGNORED_EOFS: usize = 10;
 
 pub struct Shell {
     pub args: Vec<String>,
@@ -38,63 +25,78 @@ impl Shell {
     }
 
 
-    pub fn run(&self) -> Result<(), i32> {
-        let  mut should_exit: bool = false;
-
+    pub fn run(&self) -> Result<(), Error> {
         if self.options.help && self.options.version {
-            return Err(1);
+            return Err(Error::Usage("--help and --version cannot be used together".to_string()));
         }
 
         if self.options.help {
             Self::print_help();
-            should_exit = true;
+            return Ok(());
         } else if self.options.version {
             Self::print_version();
-            should_exit = true;
+            return Ok(());
         }
 
-        while !should_exit {
+        let mut interpreter = Interpreter::new(self.options);
+        let mut eofs = 0;
+        loop {
             let mut computer_name = whoami::hostname();
             let user_name = whoami::username();
             computer_name = computer_name.replace(".localdomain", "");
             let cwd = env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("/"));
 
-            print!("{}@{} : {} $ ", user_name, computer_name, cwd.display());
+            let cwd = tilde::abbreviate(&cwd.to_string_lossy(), interpreter.vars());
+            print!("{}@{} : {} $ ", user_name, computer_name, cwd);
             stdout().flush().unwrap();
             let mut input = String::new();
-            std::io::stdin().read_line(&mut input).unwrap();
-            if input.clone().trim() == "exit" {
-                should_exit = true;
-            } else {
-                let tokens = match lex::lex(&input, self.options) {
-                    (0, tok) => tok,
-                    (exit, _) => return Err(exit),
-                };
-                let parsed = match parse::parse(tokens, self.options) {
-                    (0, parsed) => parsed,
-                    (exit, _) => return Err(exit),
-                };
-                
-                match run::run(parsed, self.options) {
-                    0 => (),
-                    exit => return Err(exit),
+            // Ctrl-D at the prompt ends the session, with the status of the last command.
+            if Self::read_line(&mut input)? == 0 {
+                println!();
+                if interpreter.options.ignoreeof && eofs < IGNORED_EOFS {
+                    eofs += 1;
+                    println!("Use \"exit\" to leave the shell.");
+                    continue;
+                }
+                return Err(Error::Exit(interpreter.status()));
+            }
+            eofs = 0;
+            // Keep reading until every here-document has seen its delimiter line.
+            while lex::needs_more_input(&input) {
+                print!("> ");
+                stdout().flush().unwrap();
+                if Self::read_line(&mut input)? == 0 {
+                    break;
                 }
             }
+
+            // Errors only end the session for `exit`. Anything else is reported, and the
+            // next command is read.
+            let result = lex::lex(&input, "stdin", interpreter.options)
+                .and_then(|tokens| parse::parse(tokens, &input, "stdin", interpreter.options))
+                .and_then(|script| run::run(&script, &mut interpreter));
+            match result {
+                Ok(_) => (),
+                Err(Error::Exit(status)) => return Err(Error::Exit(status)),
+                Err(err) => interpreter.set_status(err.report()),
+            }
         }
+    }
 
-        Ok(())
+    /// Appends a line of stdin to `input`, returning its length, which is 0 at end of file.
+    fn read_line(input: &mut String) -> Result<usize, Error> {
+        std::io::stdin().read_line(input).map_err(|source| Error::Io {
+            context: "cannot read stdin",
+            source,
+        })
     }
 
-    fn parse_shell_options(options: Vec<String>) -> Result<(Vec<String>, Options), i32> {
-        let mut ret_options = Options {
-            help: false,
-            version: false,
-            verbose: false,
-        };
+    fn parse_shell_options(options: Vec<String>) -> Result<(Vec<String>, Options), Error> {
+        let mut ret_options = Options::default();
         let iter: IntoIter<String> = options.into_iter();
 
         let raw_double_dash_options = iter.clone().filter(|x| x.starts_with("--")).collect::<Vec<String>>();
-        let raw_single_dash_options = iter.clone().filter(|x| x.starts_with('-')).collect::<Vec<String>>();
+        let raw_single_dash_options = iter.clone().filter(|x| x.starts_with('-') && !x.starts_with("--")).collect::<Vec<String>>();
         let raw_double_dash_options = raw_double_dash_options.iter().map(|x| x.trim_start_matches("--")).collect::<Vec<&str>>();
         let raw_single_dash_options = raw_single_dash_options.iter().map(|x| x.trim_start_matches('-')).collect::<Vec<&str>>();
 
@@ -109,7 +111,7 @@ impl Shell {
                     'v' => ret_options.verbose = true,
                     'q' => ret_options.verbose = false,
                     '-' => break,
-                    _ => return Err(2),
+                    _ => return Err(Error::Usage(format!("unknown option: -{c}"))),
                 }
             }
         }
@@ -120,8 +122,11 @@ impl Shell {
                 "version" => ret_options.version = true,
                 "verbose" => ret_options.verbose = true,
                 "quiet" => ret_options.verbose = false,
+                "nullglob" => ret_options.nullglob = true,
+                "failglob" => ret_options.failglob = true,
+                "ignoreeof" => ret_options.ignoreeof = true,
                 "" => break,
-                _ => return Err(3),
+                _ => return Err(Error::Usage(format!("unknown option: --{op}"))),
             }
         }
 
@@ -148,6 +153,9 @@ impl Shell {
         help.push_str("\x1b[1m  -q, --quiet\t\t\x1b[0m Do not print anything to stdout.\n");
         help.push_str("\x1b[1m  -V, --version\t\t\x1b[0m Print the version and exit.\n");
         help.push_str("\x1b[1m  -v, --verbose\t\t\x1b[0m Print debug information to stdout.\n");
+        help.push_str("\x1b[1m      --nullglob\t\x1b[0m Remove glob patterns that match no file.\n");
+        help.push_str("\x1b[1m      --failglob\t\x1b[0m Fail on glob patterns that match no file.\n");
+        help.push_str("\x1b[1m      --ignoreeof\t\x1b[0m Do not exit on end of file; use exit instead.\n");
         println!("{}", help);
     }
 
@@ -162,10 +170,11 @@ impl Shell {
 }
 
 /// The main function for the shell.
-fn main() -> Result<(), i32> {
+fn main() {
     let args = env::args().skip(1).collect::<Vec<String>>();
-    let (real_args, options) = Shell::parse_shell_options(args)?;
-    let shell = Shell::new(real_args, options);
-    shell.run()?;
-    Ok(())
+    let result = Shell::parse_shell_options(args)
+        .and_then(|(real_args, options)| Shell::new(real_args, options).run());
+    if let Err(err) = result {
+        std::process::exit(err.report());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,90 @@
+use crate::vars::Variables;
+use std::ffi::{CStr, CString};
+use std::mem::MaybeUninit;
+use std::ptr;
+
+/// Returns the directory a `~name` prefix stands for.
+///
+/// An empty name is the home directory, taken from `$HOME` or else the password database,
+/// `+` is `$PWD` and `-` is `$OLDPWD`. Any other name is looked up in the password
+/// database. Returns `None` if there is no such directory, in which case the prefix is
+/// left as it is.
+#[must_use]
+pub fn expand(name: &str, vars: &Variables) -> Option<String> {
+    match name {
+        "" => home_dir(vars),
+        "+" => vars.get("PWD").map(str::to_string).or_else(|| {
+            std::env::current_dir()
+                .ok()
+                .map(|dir| dir.to_string_lossy().into_owned())
+        }),
+        "-" => vars.get("OLDPWD").map(str::to_string),
+        user => user_home(user),
+    }
+}
+
+/// Replaces the home directory at the start of `path` with `~`.
+#[must_use]
+pub fn abbreviate(path: &str, vars: &Variables) -> String {
+    let Some(home) = home_dir(vars).filter(|home| !home.is_empty() && home != "/") else {
+        return path.to_string();
+    };
+    let home = home.trim_end_matches('/');
+    match path.strip_prefix(home) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("~{rest}"),
+        _ => path.to_string(),
+    }
+}
+
+/// Returns `$HOME`, or the home directory of the current user if it is unset.
+fn home_dir(vars: &Variables) -> Option<String> {
+    vars.get("HOME").map(str::to_string).or_else(|| {
+        // SAFETY: `getpwuid_r` only writes to the buffers it is given.
+        passwd_home(|passwd, buf, len, result| unsafe {
+            libc::getpwuid_r(libc::getuid(), passwd, buf, len, result)
+        })
+    })
+}
+
+/// Returns the home directory of a user from the password database.
+fn user_home(user: &str) -> Option<String> {
+    let user = CString::new(user).ok()?;
+    // SAFETY: `user` is a valid C string and `getpwnam_r` only writes to the buffers it is
+    // given.
+    passwd_home(|passwd, buf, len, result| unsafe {
+        libc::getpwnam_r(user.as_ptr(), passwd, buf, len, result)
+    })
+}
+
+/// Calls one of the reentrant password database lookups, growing the string buffer until
+/// the entry fits, and returns the home directory of the entry found.
+fn passwd_home(
+    mut lookup: impl FnMut(
+        *mut libc::passwd,
+        *mut libc::c_char,
+        usize,
+        *mut *mut libc::passwd,
+    ) -> libc::c_int,
+) -> Option<String> {
+    let mut buf = vec![0; 1024];
+    loop {
+        let mut passwd = MaybeUninit::<libc::passwd>::zeroed();
+        let mut result = ptr::null_mut();
+        match lookup(
+            passwd.as_mut_ptr(),
+            buf.as_mut_ptr(),
+            buf.len(),
+            &raw mut result,
+        ) {
+            0 if result.is_null() => return None,
+            0 => {
+                // SAFETY: on success `result` points to `passwd`, whose strings live in
+                // `buf`.
+                let dir = unsafe { CStr::from_ptr((*result).pw_dir) };
+                return dir.to_str().ok().map(str::to_string);
+            }
+            libc::ERANGE if buf.len() < 1 << 20 => buf.resize(buf.len() * 2, 0),
+            _ => return None,
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use crate::error::Error;
+use std::collections::HashMap;
+
+/// The table of shell variables.
+#[derive(Clone, Debug, Default)]
+pub struct Variables {
+    values: HashMap<String, Variable>,
+}
+
+/// A shell variable: its value, if it has one, and its attributes.
+///
+/// A variable without a value only exists to hold attributes, as after `export name` or
+/// `readonly name` of an unset variable.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Variable {
+    pub value: Option<String>,
+    /// Whether the variable is passed to the environment of commands.
+    pub exported: bool,
+    /// Whether the variable can no longer be assigned or unset.
+    pub readonly: bool,
+}
+
+impl Variables {
+    /// Creates a table holding the variables of the process environment, all exported.
+    #[must_use]
+    pub fn from_env() -> Self {
+        Self {
+            values: std::env::vars()
+                .map(|(name, value)| {
+                    let variable = Variable {
+                        value: Some(value),
+                        exported: true,
+                        readonly: false,
+                    };
+                    (name, variable)
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the value of a variable, if it is set.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name)?.value.as_deref()
+    }
+
+    /// Sets a variable, creating it if needed. Its attributes are kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Readonly`] if the variable is readonly.
+    pub fn set(&mut self, name: &str, value: String) -> Result<(), Error> {
+        let variable = self.values.entry(name.to_string()).or_default();
+        if variable.readonly {
+            return Err(Error::Readonly(name.to_string()));
+        }
+        variable.value = Some(value);
+        Ok(())
+    }
+
+    /// Replaces a variable along with its attributes, or removes it if `variable` is
+    /// `None`, returning what it was.
+    ///
+    /// Unlike [`Variables::set`], this ignores the readonly attribute, so that a variable
+    /// saved earlier can be put back.
+    pub fn replace(&mut self, name: &str, variable: Option<Variable>) -> Option<Variable> {
+        match variable {
+            Some(variable) => self.values.insert(name.to_string(), variable),
+            None => self.values.remove(name),
+        }
+    }
+
+    /// Removes a variable, along with its attributes.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Readonly`] if the variable is readonly.
+    pub fn unset(&mut self, name: &str) -> Result<(), Error> {
+        if self.is_readonly(name) {
+            return Err(Error::Readonly(name.to_string()));
+        }
+        self.values.remove(name);
+        Ok(())
+    }
+
+    /// Sets whether a variable is passed to the environment of commands, creating it
+    /// without a value if needed.
+    pub fn set_exported(&mut self, name: &str, exported: bool) {
+        self.values.entry(name.to_string()).or_default().exported = exported;
+    }
+
+    /// Makes a variable readonly, creating it without a value if needed.
+    pub fn set_readonly(&mut self, name: &str) {
+        self.values.entry(name.to_string()).or_default().readonly = true;
+    }
+
+    /// Returns whether a variable is readonly.
+    #[must_use]
+    pub fn is_readonly(&self, name: &str) -> bool {
+        self.values
+            .get(name)
+            .is_some_and(|variable| variable.readonly)
+    }
+
+    /// Returns every variable, sorted by name.
+    #[must_use]
+    pub fn sorted(&self) -> Vec<(&str, &Variable)> {
+        let mut variables = self
+            .values
+            .iter()
+            .map(|(name, variable)| (name.as_str(), variable))
+            .collect::<Vec<_>>();
+        variables.sort_unstable_by_key(|&(name, _)| name);
+        variables
+    }
+
+    /// Returns the exported variables that have a value, which make up the environment of
+    /// commands.
+    pub fn environment(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values.iter().filter_map(|(name, variable)| {
+            let value = variable.value.as_deref().filter(|_| variable.exported)?;
+            Some((name.as_str(), value))
+        })
+    }
+}
+
+/// Returns whether `c` is the name of a special parameter, such as `?` in `$?`.
+#[must_use]
+pub const fn is_special_parameter(c: char) -> bool {
+    matches!(c, '@' | '*' | '#' | '?' | '-' | '$' | '!')
+}
+
+/// Returns whether `name` is a valid variable name: a letter or underscore followed by
+/// letters, digits and underscores.
+#[must_use]
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}